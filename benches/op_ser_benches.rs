@@ -4,6 +4,8 @@ use lever::table::ltable::LTable;
 use lever::txn::prelude::*;
 use rand::prelude::*;
 use rand_distr::Pareto;
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn pure_read(txn: Txn, tvar: TVar<LTable<String, String>>) -> LTable<String, String> {
     let res = txn.begin(|t: &mut Txn| t.read(&tvar));
@@ -15,7 +17,7 @@ fn bench_pure_reads(c: &mut Criterion) {
     let mut ltable = LTable::<String, String>::create("pure_reads".to_owned());
     ltable.insert("Interstellar".into(), "Gravity".into());
 
-    let txn = TxnManager::manager().txn_build(
+    let txn = TxnManager::global().txn_build(
         TransactionConcurrency::Optimistic,
         TransactionIsolation::Serializable,
         100_usize,
@@ -53,7 +55,7 @@ fn bench_rw_pareto(c: &mut Criterion) {
     let mut ltable = LTable::<String, String>::create("rw_pareto".to_owned());
     ltable.insert("Interstellar".into(), "Gravity".into());
 
-    let txn = TxnManager::manager().txn_build(
+    let txn = TxnManager::global().txn_build(
         TransactionConcurrency::Optimistic,
         TransactionIsolation::Serializable,
         100_usize,
@@ -88,7 +90,7 @@ fn bench_pure_write(c: &mut Criterion) {
     let mut ltable = LTable::<String, String>::create("pure_writes".to_owned());
     ltable.insert("Interstellar".into(), "Gravity".into());
 
-    let txn = TxnManager::manager().txn_build(
+    let txn = TxnManager::global().txn_build(
         TransactionConcurrency::Optimistic,
         TransactionIsolation::Serializable,
         100_usize,
@@ -108,9 +110,67 @@ fn bench_pure_write(c: &mut Criterion) {
     });
 }
 
+fn disjoint_writes(manager: &TxnManager, threads: u64, iters: u64) -> Duration {
+    let txn = manager.txn_build(
+        TransactionConcurrency::Optimistic,
+        TransactionIsolation::RepeatableRead,
+        100_usize,
+        1_usize,
+        "disjoint_writes".into(),
+    );
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let txn = txn.clone();
+            // Every thread writes its own variable, commits only contend on the clock.
+            let mut tvar = manager.tvar(0_u64);
+
+            thread::spawn(move || {
+                for i in 0..iters / threads {
+                    let _ = txn.begin(|t| t.write(&mut tvar, i));
+                }
+            })
+        })
+        .collect();
+
+    workers.into_iter().for_each(|w| w.join().unwrap());
+    start.elapsed()
+}
+
+fn bench_clock_strategies(c: &mut Criterion) {
+    let threads = num_cpus().max(2);
+    let strategies = vec![
+        ("gv1", ClockStrategy::Gv1),
+        ("gv4", ClockStrategy::Gv4),
+        ("gv5", ClockStrategy::Gv5),
+        ("gv6", ClockStrategy::Gv6 { period: 32 }),
+        (
+            "sharded",
+            ClockStrategy::Sharded {
+                shards: threads as usize,
+            },
+        ),
+    ];
+
+    for (name, strategy) in strategies.into_iter() {
+        let manager = TxnManager::with_clock_strategy(strategy);
+
+        c.bench_function(&format!("clock_{}_disjoint_writes", name), |b| {
+            b.iter_custom(|iters| disjoint_writes(&manager, threads, iters))
+        });
+    }
+}
+
+fn num_cpus() -> u64 {
+    thread::available_parallelism()
+        .map(|n| n.get() as u64)
+        .unwrap_or(1)
+}
+
 criterion_group! {
     name = op_ser_benches;
     config = Criterion::default();
-    targets = bench_pure_reads, bench_rw_pareto, bench_pure_write
+    targets = bench_pure_reads, bench_rw_pareto, bench_pure_write, bench_clock_strategies
 }
 criterion_main!(op_ser_benches);
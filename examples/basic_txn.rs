@@ -34,7 +34,7 @@ fn main() {
     // You don't necessarily need to use convenience methods.
 
     txn.begin(|t| {
-        let mut churned = *customers;
+        let mut churned = *customers.get();
         churned -= 123_000;
         t.write(&mut customers, churned);
     });
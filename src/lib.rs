@@ -73,18 +73,20 @@ pub struct Lever(Arc<TxnManager>);
 ///
 /// Instantiate lever instance
 pub fn lever() -> Lever {
-    Lever(TxnManager::manager())
+    Lever(TxnManager::global())
 }
 
 impl Lever {
     ///
     /// Builder method for transactional optimistic, repeatable read in-memory table.
+    ///
+    /// The table is of the manager of this instance, see [LOTable::in_manager].
     pub fn new_lotable<K, V>(&self) -> LOTable<K, V>
     where
         K: 'static + PartialEq + Eq + Hash + Clone + Send + Sync + Ord,
         V: 'static + Clone + Send + Sync,
     {
-        LOTable::new()
+        LOTable::in_manager(&self.0)
     }
 
     ///
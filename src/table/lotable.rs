@@ -2,19 +2,23 @@ use crate::sync::atomics::AtomicBox;
 use crate::txn::prelude::*;
 
 use std::collections::hash_map::{Iter, Keys, RandomState};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
 use std::collections::hash_map;
 use std::fmt;
 use std::hash::Hash;
 use std::hash::{BuildHasher, Hasher};
+use std::io;
 use std::ptr::NonNull;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 const DEFAULT_CAP: usize = 1024;
 
+/// Frame of the latch, holding the entries of the keys which are hashed into it
+type Frame<K, V> = TVar<Arc<AtomicBox<Container<K, V>>>>;
+
 #[derive(Clone)]
 ///
 /// Lever Transactional Table implementation with [Optimistic](TransactionConcurrency::Optimistic)
@@ -32,6 +36,9 @@ where
     txn_man: Arc<TxnManager>,
     txn: Arc<Txn>,
     hash_builder: S,
+    /// Changes are written in the transactions of the manager, so they are part of the
+    /// enclosing transactions of it, and logged along with their commits if it is durable
+    transactional: bool,
 }
 
 impl<K, V> LOTable<K, V, RandomState>
@@ -39,13 +46,92 @@ where
     K: PartialEq + Eq + Hash + Clone + Send + Sync,
     V: Clone + Send + Sync,
 {
+    ///
+    /// Table which runs on a manager of its own. Its changes are committed on their own, even
+    /// when they are made inside a transaction, so they aren't rolled back or retried along
+    /// with it. See [LOTable::in_manager] for the tables whose changes are part of the
+    /// enclosing transactions.
     pub fn new() -> Self {
         Self::with_capacity(DEFAULT_CAP)
     }
 
+    ///
+    /// Table with the given capacity which runs on a manager of its own, see [LOTable::new].
     pub fn with_capacity(cap: usize) -> Self {
         Self::with_capacity_and_hasher(cap, RandomState::new())
     }
+
+    ///
+    /// Table of the given manager whose changes are written in the transactions of it.
+    ///
+    /// Changes made inside a transaction of the manager are part of it, they are committed,
+    /// rolled back and retried along with it. Changes made inside a transaction of any other
+    /// manager are committed on their own, see [Txn::try_begin].
+    pub fn in_manager(manager: &Arc<TxnManager>) -> Self {
+        let mut table = Self::with_manager(DEFAULT_CAP, RandomState::new(), manager.clone());
+        table.transactional = true;
+        table
+    }
+}
+
+impl<K, V> LOTable<K, V, RandomState>
+where
+    K: PartialEq + Eq + Hash + Clone + Send + Sync + Durable,
+    V: Clone + Send + Sync + Durable,
+{
+    ///
+    /// Table of the given [durable](TxnManager::durable) manager whose changes are logged to
+    /// its redo log under the given name. Table starts with the entries logged under the name,
+    /// if there are any.
+    ///
+    /// Changes are written in the transactions of the manager, and they are logged in the
+    /// commit records of them. Changes made in a transaction of the manager are part of it,
+    /// they are logged atomically with the variables it writes.
+    ///
+    /// Names identify the tables across the restarts, so they have to be unique.
+    pub fn durable(manager: &Arc<TxnManager>, name: &str) -> io::Result<Self> {
+        let wal = manager.wal()?;
+
+        let mut table = Self::with_manager(DEFAULT_CAP, RandomState::new(), manager.clone());
+        table.transactional = true;
+
+        // Every frame is logged as a variable of the manager. Keys are hashed differently in
+        // every run, so the entries of all the frames are recovered together.
+        let frames = table.frames();
+        let mut recovered = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let frame_name = format!("{}/{}", name, i);
+            if let Some(entries) = wal.recover::<HashMap<K, V>>(&frame_name)? {
+                recovered.extend(entries);
+            }
+            wal.register(&frame_name, frame)?;
+        }
+
+        if !recovered.is_empty() {
+            let mut entries: HashMap<u64, HashMap<K, V>> = HashMap::new();
+            for (k, v) in recovered {
+                entries
+                    .entry(table.seek_tvar(&k).id)
+                    .or_default()
+                    .insert(k, v);
+            }
+
+            // Entries are logged again in their frames in a single commit, which supersedes
+            // the frames logged before.
+            table
+                .txn
+                .begin(|t| {
+                    for frame in frames.iter() {
+                        let mut frame = frame.clone();
+                        let entries = entries.get(&frame.id).cloned().unwrap_or_default();
+                        t.write(&mut frame, Arc::new(AtomicBox::new(Container(entries))));
+                    }
+                })
+                .map_err(io::Error::other)?;
+        }
+
+        Ok(table)
+    }
 }
 
 impl<K, V, S> LOTable<K, V, S>
@@ -55,10 +141,10 @@ where
     S: BuildHasher,
 {
     fn with_capacity_and_hasher(cap: usize, hasher: S) -> LOTable<K, V, S> {
-        let txn_man = Arc::new(TxnManager {
-            txid: Arc::new(AtomicU64::new(GLOBAL_VCLOCK.load(Ordering::SeqCst))),
-        });
+        Self::with_manager(cap, hasher, TxnManager::isolated())
+    }
 
+    fn with_manager(cap: usize, hasher: S, txn_man: Arc<TxnManager>) -> LOTable<K, V, S> {
         let txn: Arc<Txn> = Arc::new(txn_man.txn_build(
             TransactionConcurrency::Optimistic,
             TransactionIsolation::RepeatableRead,
@@ -68,10 +154,11 @@ where
         ));
 
         Self {
-            latch: vec![TVar::new(Arc::new(AtomicBox::new(Container(HashMap::default())))); cap],
+            latch: vec![txn_man.tvar(Arc::new(AtomicBox::new(Container(HashMap::default())))); cap],
             txn_man,
             txn,
             hash_builder: hasher,
+            transactional: false,
         }
     }
 
@@ -79,31 +166,44 @@ where
     pub fn insert(&self, k: K, v: V) -> Result<Arc<Option<V>>> {
         let tvar = self.seek_tvar(&k);
 
-        let container = self.txn.begin(|t| t.read(&tvar))?;
-
-        let previous: Arc<AtomicBox<Option<V>>> = Arc::new(AtomicBox::new(None));
-        container.replace_with(|r| {
-            let mut entries = r.0.clone();
-            let p = entries.insert(k.clone(), v.clone());
-            previous.replace_with(|_| p.clone());
-            Container(entries)
-        });
-
-        previous.extract()
+        self.modify(tvar, |entries| entries.insert(k.clone(), v.clone()))
     }
 
     #[inline]
     pub fn remove(&self, k: &K) -> Result<Arc<Option<V>>> {
         let tvar = self.seek_tvar(&k);
 
+        self.modify(tvar, |entries| entries.remove(k))
+    }
+
+    ///
+    /// Changes the entries of the given frame with the given closure, and returns the entry it
+    /// has replaced.
+    fn modify<F>(&self, mut tvar: Frame<K, V>, f: F) -> Result<Arc<Option<V>>>
+    where
+        F: Fn(&mut HashMap<K, V>) -> Option<V>,
+    {
+        // Frames are written in a transaction, so the change is part of the enclosing one, and
+        // it is logged in its commit.
+        if self.transactional {
+            let previous = self.txn.begin(|t| {
+                let mut entries = t.read(&tvar).get().0.clone();
+                let p = f(&mut entries);
+                t.write(&mut tvar, Arc::new(AtomicBox::new(Container(entries))));
+                p
+            })?;
+
+            return Ok(Arc::new(previous));
+        }
+
         let container = self.txn.begin(|t| t.read(&tvar))?;
 
         let previous: Arc<AtomicBox<Option<V>>> = Arc::new(AtomicBox::new(None));
         container.replace_with(|r| {
-            let mut c = r.0.clone();
-            let p = c.remove(k);
+            let mut entries = r.0.clone();
+            let p = f(&mut entries);
             previous.replace_with(|_| p.clone());
-            Container(c)
+            Container(entries)
         });
 
         previous.extract()
@@ -114,7 +214,7 @@ where
         let tvar = self.seek_tvar(k);
 
         self.txn
-            .begin(|t| {
+            .begin_read_only(|t| {
                 let container = t.read(&tvar);
                 let entries = container.get();
                 entries.0.get(k).cloned()
@@ -160,7 +260,7 @@ where
         let tvar = self.seek_tvar(&k);
 
         self.txn
-            .begin(|t| {
+            .begin_read_only(|t| {
                 let container = t.read(&tvar);
                 container.get().0.contains_key(k)
             })
@@ -173,7 +273,7 @@ where
             .first()
             .map(move |b| {
                 self.txn
-                    .begin(|t| {
+                    .begin_read_only(|t| {
                         let container = t.read(&b);
                         container.get().0.len()
                     })
@@ -196,6 +296,21 @@ where
 
     #[inline]
     pub fn clear(&self) {
+        if self.transactional {
+            // Every frame is emptied in the same commit.
+            let frames = self.frames();
+            let _ = self.txn.begin(|t| {
+                for frame in frames.iter() {
+                    let mut frame = frame.clone();
+                    t.write(
+                        &mut frame,
+                        Arc::new(AtomicBox::new(Container(HashMap::default()))),
+                    );
+                }
+            });
+            return;
+        }
+
         self.latch.iter().for_each(move |b| {
             let _ = self.txn.begin(|t| {
                 let container = t.read(&b);
@@ -213,7 +328,7 @@ where
             .iter()
             .flat_map(move |b| {
                 self.txn
-                    .begin(|t| {
+                    .begin_read_only(|t| {
                         let container = t.read(&b);
                         container
                             .get()
@@ -237,7 +352,7 @@ where
             .iter()
             .flat_map(move |b| {
                 self.txn
-                    .begin(|t| {
+                    .begin_read_only(|t| {
                         let container = t.read(&b);
                         container
                             .get()
@@ -272,6 +387,17 @@ where
         }
     }
 
+    ///
+    /// Distinct frames of the latch, in the order they appear in it.
+    fn frames(&self) -> Vec<Frame<K, V>> {
+        let mut seen = HashSet::new();
+        self.latch
+            .iter()
+            .filter(|frame| seen.insert(frame.id))
+            .cloned()
+            .collect()
+    }
+
     ////////////////////////////////////////////////////////////////////////////////
     ////////// Transactional Area
     ////////////////////////////////////////////////////////////////////////////////
@@ -287,6 +413,20 @@ where
     K: PartialEq + Hash + Clone + Send + Sync,
     V: Clone + Send + Sync;
 
+impl<K, V> Durable for Container<K, V>
+where
+    K: PartialEq + Eq + Hash + Clone + Send + Sync + Durable,
+    V: Clone + Send + Sync + Durable,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Container(HashMap::decode(buf)?))
+    }
+}
+
 impl<K, V, S> Default for LOTable<K, V, S>
 where
     K: 'static + PartialEq + Eq + Hash + Clone + Send + Sync,
@@ -374,6 +514,8 @@ where
 #[cfg(test)]
 mod lotable_tests {
     use super::LOTable;
+    use crate::txn::errors::{TxnError, TxnResult};
+    use crate::txn::prelude::*;
 
     #[test]
     fn iter_generator() {
@@ -448,4 +590,41 @@ mod lotable_tests {
             assert_eq!(reskeys.len(), i + 1);
         });
     }
+
+    #[test]
+    fn lotable_in_manager() {
+        let manager = TxnManager::isolated();
+        let table: LOTable<u64, u64> = LOTable::in_manager(&manager);
+        let txn = manager.txn_build(
+            TransactionConcurrency::Optimistic,
+            TransactionIsolation::RepeatableRead,
+            100_usize,
+            1_usize,
+            "lotable_in_manager".into(),
+        );
+
+        // Changes are rolled back along with the enclosing transaction.
+        let res: TxnResult<()> = txn.try_begin(|t| {
+            table.insert(1, 100).unwrap();
+            assert_eq!(table.get(&1), Some(100));
+            t.abort()
+        });
+        assert!(matches!(res, Err(TxnError::Abort)));
+        assert_eq!(table.get(&1), None);
+
+        txn.begin(|_| {
+            table.insert(2, 200).unwrap();
+        })
+        .unwrap();
+        assert_eq!(table.get(&2), Some(200));
+
+        // Tables of another manager commit on their own.
+        let other: LOTable<u64, u64> = LOTable::new();
+        let res: TxnResult<()> = txn.try_begin(|t| {
+            other.insert(3, 300).unwrap();
+            t.abort()
+        });
+        assert!(matches!(res, Err(TxnError::Abort)));
+        assert_eq!(other.get(&3), Some(300));
+    }
 }
@@ -37,9 +37,7 @@ where
     pub fn create(name: String) -> Self {
         // TODO: Separate data from the latch access.
 
-        let txn_man = Arc::new(TxnManager {
-            txid: Arc::new(AtomicU64::new(GLOBAL_VCLOCK.load(Ordering::SeqCst))),
-        });
+        let txn_man = TxnManager::global();
 
         Self {
             latch: HashMap::with_capacity(100),
@@ -257,7 +255,7 @@ mod ltable_tests {
         ltable1.insert("alice1_init".into(), 50);
         ltable2.insert("alice2_init".into(), 50);
 
-        let txn = TxnManager::manager().txn_build(
+        let txn = TxnManager::global().txn_build(
             TransactionConcurrency::Optimistic,
             TransactionIsolation::Serializable,
             100_usize,
@@ -316,9 +314,9 @@ mod ltable_tests {
                                 let r = txn
                                     .begin(|_t| {
                                         (
-                                            sum_table(&*alice_accounts[0]),
-                                            sum_table(&*alice_accounts[1]),
-                                            sum_table(&*bob_account),
+                                            sum_table(&alice_accounts[0].get()),
+                                            sum_table(&alice_accounts[1].get()),
+                                            sum_table(&bob_account.get()),
                                         )
                                     })
                                     .unwrap();
@@ -340,13 +338,22 @@ mod ltable_tests {
                                 );
 
                                 // reset accounts
-                                txn.begin(|_t| {
-                                    (*alice_accounts[0]).clear();
-                                    (*alice_accounts[0]).insert("alice1_init".into(), 50);
-                                    (*alice_accounts[1]).clear();
-                                    (*alice_accounts[1]).insert("alice2_init".into(), 50);
-                                    (*bob_account).clear();
-                                });
+                                txn.begin(|t| {
+                                    let mut a0 = t.read(&alice_accounts[0]);
+                                    a0.clear();
+                                    a0.insert("alice1_init".into(), 50);
+                                    t.write(&mut alice_accounts[0], a0);
+
+                                    let mut a1 = t.read(&alice_accounts[1]);
+                                    a1.clear();
+                                    a1.insert("alice2_init".into(), 50);
+                                    t.write(&mut alice_accounts[1], a1);
+
+                                    let mut b = t.read(&bob_account);
+                                    b.clear();
+                                    t.write(&mut bob_account, b);
+                                })
+                                .unwrap();
                             }
                         }
                     })
@@ -371,7 +378,7 @@ mod ltable_tests {
         ltable1.insert("alice1_init".into(), 50);
         ltable2.insert("alice2_init".into(), 50);
 
-        let txn = TxnManager::manager().txn_build(
+        let txn = TxnManager::global().txn_build(
             TransactionConcurrency::Optimistic,
             TransactionIsolation::Serializable,
             100_usize,
@@ -428,9 +435,9 @@ mod ltable_tests {
                                 let r = txn
                                     .begin(|_t| {
                                         (
-                                            sum_table(&*alice_accounts[0]),
-                                            sum_table(&*alice_accounts[1]),
-                                            sum_table(&*bob_account),
+                                            sum_table(&alice_accounts[0].get()),
+                                            sum_table(&alice_accounts[1].get()),
+                                            sum_table(&bob_account.get()),
                                         )
                                     })
                                     .unwrap();
@@ -452,13 +459,22 @@ mod ltable_tests {
                                 );
 
                                 // reset accounts
-                                txn.begin(|_t| {
-                                    (*alice_accounts[0]).clear();
-                                    (*alice_accounts[0]).insert("alice1_init".into(), 50);
-                                    (*alice_accounts[1]).clear();
-                                    (*alice_accounts[1]).insert("alice2_init".into(), 50);
-                                    (*bob_account).clear();
-                                });
+                                txn.begin(|t| {
+                                    let mut a0 = t.read(&alice_accounts[0]);
+                                    a0.clear();
+                                    a0.insert("alice1_init".into(), 50);
+                                    t.write(&mut alice_accounts[0], a0);
+
+                                    let mut a1 = t.read(&alice_accounts[1]);
+                                    a1.clear();
+                                    a1.insert("alice2_init".into(), 50);
+                                    t.write(&mut alice_accounts[1], a1);
+
+                                    let mut b = t.read(&bob_account);
+                                    b.clear();
+                                    t.write(&mut bob_account, b);
+                                })
+                                .unwrap();
                             }
                         }
                     })
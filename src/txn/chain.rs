@@ -0,0 +1,205 @@
+use crate::txn::constants::DEFAULT_TX_MAX_VERSIONS;
+use crate::txn::version::Committed;
+
+use crossbeam_epoch as epoch;
+use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+
+///
+/// Committed versions of a transactional variable, from the newest to the oldest.
+///
+/// Chain has a single writer at a time, versions are published while the lock of the variable
+/// is held. Readers walk the chain without locking. Versions that are not visible to any
+/// reader anymore are reclaimed when the readers that might be still walking over them are gone.
+pub(crate) struct VersionChain<T> {
+    head: Atomic<Node<T>>,
+}
+
+struct Node<T> {
+    version: Committed<T>,
+    prev: Atomic<Node<T>>,
+}
+
+impl<T> VersionChain<T> {
+    pub(crate) fn new(version: Committed<T>) -> Self {
+        Self {
+            head: Atomic::new(Node {
+                version,
+                prev: Atomic::null(),
+            }),
+        }
+    }
+
+    ///
+    /// Latest committed version.
+    pub(crate) fn latest(&self) -> Committed<T> {
+        let guard = epoch::pin();
+        let head = self.head.load(Acquire, &guard);
+
+        // SAFETY: Chain always has at least one version.
+        unsafe { head.deref() }.version.clone()
+    }
+
+    ///
+    /// Version that is visible to a reader which reads at the given timestamp, if it is still
+    /// retained.
+    pub(crate) fn at(&self, ts: u64) -> Option<Committed<T>> {
+        let guard = epoch::pin();
+        let mut node = self.head.load(Acquire, &guard);
+
+        // SAFETY: Nodes are reclaimed only after every pinned reader is unpinned.
+        while let Some(n) = unsafe { node.as_ref() } {
+            if n.version.stamp <= ts {
+                return Some(n.version.clone());
+            }
+
+            node = n.prev.load(Acquire, &guard);
+        }
+
+        None
+    }
+
+    ///
+    /// Retained versions which are committed after the given timestamp, from the newest to the
+    /// oldest.
+    pub(crate) fn since(&self, ts: u64) -> Vec<Committed<T>> {
+        let guard = epoch::pin();
+        let mut node = self.head.load(Acquire, &guard);
+        let mut versions = vec![];
+
+        while let Some(n) = unsafe { node.as_ref() } {
+            if n.version.stamp <= ts {
+                break;
+            }
+
+            versions.push(n.version.clone());
+            node = n.prev.load(Acquire, &guard);
+        }
+
+        versions
+    }
+
+    ///
+    /// Retained versions, from the newest to the oldest.
+    pub(crate) fn versions(&self) -> Vec<Committed<T>> {
+        self.since(0).into_iter().chain(self.at(0)).collect()
+    }
+
+    ///
+    /// Publishes the given version as the latest one.
+    ///
+    /// Versions that are older than the version visible at the `horizon` are collected, and at most
+    /// [DEFAULT_TX_MAX_VERSIONS] versions are retained.
+    pub(crate) fn publish(&self, version: Committed<T>, horizon: u64) {
+        let guard = epoch::pin();
+        let head = self.head.load(Acquire, &guard);
+
+        let node = Owned::new(Node {
+            version,
+            prev: Atomic::from(head),
+        })
+        .into_shared(&guard);
+        self.head.store(node, Release);
+
+        self.collect(node, horizon, &guard);
+    }
+
+    fn collect(&self, head: Shared<Node<T>>, horizon: u64, guard: &Guard) {
+        let mut node = head;
+        let mut retained = 1;
+
+        // SAFETY: Only the single writer unlinks nodes, and it is the one walking here.
+        while let Some(n) = unsafe { node.as_ref() } {
+            if n.version.stamp <= horizon || retained == DEFAULT_TX_MAX_VERSIONS {
+                let mut tail = n.prev.swap(Shared::null(), AcqRel, guard);
+
+                while let Some(t) = unsafe { tail.as_ref() } {
+                    let prev = t.prev.load(Acquire, guard);
+                    // SAFETY: Tail is unlinked, new readers can't reach it anymore.
+                    unsafe { guard.defer_destroy(tail) };
+                    tail = prev;
+                }
+
+                return;
+            }
+
+            node = n.prev.load(Acquire, guard);
+            retained += 1;
+        }
+    }
+
+    ///
+    /// Number of retained versions.
+    pub(crate) fn len(&self) -> usize {
+        let guard = epoch::pin();
+        let mut node = self.head.load(Acquire, &guard);
+        let mut len = 0;
+
+        while let Some(n) = unsafe { node.as_ref() } {
+            node = n.prev.load(Acquire, &guard);
+            len += 1;
+        }
+
+        len
+    }
+}
+
+impl<T> Drop for VersionChain<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY: Chain is dropped by its last owner, nobody can be reading it.
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Relaxed, guard);
+
+            while !node.is_null() {
+                let prev = node.deref().prev.load(Relaxed, guard);
+                drop(node.into_owned());
+                node = prev;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod chain_tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn version(stamp: u64) -> Committed<u64> {
+        Committed {
+            stamp,
+            data: Arc::new(stamp),
+            writer: None,
+        }
+    }
+
+    #[test]
+    fn chain_snapshot_reads() {
+        let chain = VersionChain::new(version(1));
+        chain.publish(version(3), 1);
+        chain.publish(version(5), 1);
+
+        assert_eq!(chain.latest().stamp, 5);
+        assert_eq!(chain.at(4).map(|v| v.stamp), Some(3));
+        assert_eq!(chain.at(2).map(|v| v.stamp), Some(1));
+        assert!(chain.at(0).is_none());
+
+        let since: Vec<u64> = chain.since(1).iter().map(|v| v.stamp).collect();
+        assert_eq!(since, vec![5, 3]);
+    }
+
+    #[test]
+    fn chain_collects_versions_beyond_horizon() {
+        let chain = VersionChain::new(version(1));
+        chain.publish(version(3), 1);
+        chain.publish(version(5), 3);
+        assert_eq!(chain.len(), 2);
+
+        // Nobody reads in the past.
+        chain.publish(version(7), u64::MAX);
+        assert_eq!(chain.len(), 1);
+
+        (8..100).for_each(|stamp| chain.publish(version(stamp), 0));
+        assert_eq!(chain.len(), DEFAULT_TX_MAX_VERSIONS);
+    }
+}
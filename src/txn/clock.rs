@@ -0,0 +1,371 @@
+use lazy_static::*;
+use parking_lot::Mutex;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+lazy_static! {
+    /// Shard index of the next thread
+    static ref SHARDS: AtomicUsize = AtomicUsize::new(0);
+}
+
+thread_local! {
+    static LOCAL_SHARD: usize = SHARDS.fetch_add(1, Ordering::Relaxed);
+    static LOCAL_COMMITS: Cell<u64> = const { Cell::new(0) };
+}
+
+///
+/// Scheme of the version clock which dispenses the write timestamps of the commits.
+///
+/// Every commit takes a write timestamp, so the clock is the hottest shared location under
+/// write-heavy workloads. Schemes other than [ClockStrategy::Gv1] trade the uniqueness of the
+/// write timestamps, or the eagerness of the clock, for less contention on it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ClockStrategy {
+    ///
+    /// Every commit increments the clock. Write timestamps are unique.
+    #[default]
+    Gv1,
+    ///
+    /// Pass on failure. Commit tries to increment the clock once, and if some other commit
+    /// increments it meanwhile, shares its write timestamp instead of trying again.
+    Gv4,
+    ///
+    /// Lazy increment. Commits stamp their versions one past the clock without incrementing it.
+    /// Clock is moved forward only by the transactions which read a version beyond it.
+    ///
+    /// Snapshots don't move the clock, so a snapshot read may miss the latest commits of the
+    /// other threads until some transaction moves the clock past them. Every thread observes
+    /// its own commits.
+    Gv5,
+    ///
+    /// Lazy increment like [ClockStrategy::Gv5], and every `period`th commit of a thread
+    /// increments the clock like [ClockStrategy::Gv4], which bounds how far snapshots lag
+    /// behind.
+    Gv6 {
+        /// Number of the commits of a thread per increment
+        period: u64,
+    },
+    ///
+    /// Clock is split into the given number of shards, and threads are spread over them.
+    /// Commit reads all shards and only writes its own, so the commits on different shards
+    /// don't contend. Reading the clock takes the maximum of the shards.
+    Sharded {
+        /// Number of shards
+        shards: usize,
+    },
+}
+
+///
+/// Clock value on its own cache line.
+#[derive(Default)]
+#[repr(align(128))]
+struct Shard(AtomicU64);
+
+///
+/// Number of the commits that can publish at the same time, more of them wait for a free slot.
+const PUBLISHING_SLOTS: usize = 64;
+
+///
+/// Version clock of a transaction manager, along with the id space of its transactional
+/// variables.
+///
+/// Timestamps of different clocks are not comparable, so a transaction only accesses the
+/// variables bound to the clock of its manager.
+pub(crate) struct Clock {
+    strategy: ClockStrategy,
+    /// Version clock, only the first one is used unless the clock is sharded
+    shards: Vec<Shard>,
+    /// TVar ids
+    tvars: AtomicU64,
+    /// Read timestamps of the ongoing snapshot reads, along with the number of readers at each
+    readers: Mutex<BTreeMap<u64, usize>>,
+    /// Oldest read timestamp of the ongoing snapshot reads, mirrors `readers` for the commits
+    horizon: AtomicU64,
+    /// Commits that are publishing, a slot is odd while a commit holds it. Every commit
+    /// increments its slot twice, so a change of the slot means the commit holding it is over.
+    publishing: Vec<Shard>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(ClockStrategy::default())
+    }
+}
+
+impl Clock {
+    pub(crate) fn new(strategy: ClockStrategy) -> Self {
+        let shards = match strategy {
+            ClockStrategy::Sharded { shards } => shards.max(1),
+            _ => 1,
+        };
+
+        Self {
+            strategy,
+            shards: (0..shards).map(|_| Shard::default()).collect(),
+            tvars: AtomicU64::default(),
+            readers: Mutex::new(BTreeMap::new()),
+            horizon: AtomicU64::new(u64::MAX),
+            publishing: (0..PUBLISHING_SLOTS).map(|_| Shard::default()).collect(),
+        }
+    }
+
+    ///
+    /// Latest timestamp that is safe to read at.
+    pub(crate) fn now(&self) -> u64 {
+        match self.strategy {
+            ClockStrategy::Sharded { .. } => self
+                .shards
+                .iter()
+                .map(|s| s.0.load(Ordering::SeqCst))
+                .max()
+                .unwrap_or_default(),
+            _ => self.clock().load(Ordering::SeqCst),
+        }
+    }
+
+    ///
+    /// Moves the clock at least to the given timestamp, and returns the latest timestamp.
+    pub(crate) fn advance(&self, ts: u64) -> u64 {
+        let now = self.now();
+        if now >= ts {
+            return now;
+        }
+
+        self.local().fetch_max(ts, Ordering::SeqCst);
+        self.now()
+    }
+
+    ///
+    /// Dispenses a new write timestamp. It is greater than the given timestamp, the latest one
+    /// of the variables that are written, and than the timestamp of every version that is
+    /// published already.
+    pub(crate) fn tick(&self, latest: u64) -> u64 {
+        let wts = match self.strategy {
+            ClockStrategy::Gv1 => self.increment(),
+            ClockStrategy::Gv4 => self.pass_on_failure(),
+            ClockStrategy::Gv5 => self.now().saturating_add(1),
+            ClockStrategy::Gv6 { period } => {
+                let commits = LOCAL_COMMITS.with(|c| {
+                    c.set(c.get().wrapping_add(1));
+                    c.get()
+                });
+
+                if commits % period.max(1) == 0 {
+                    self.pass_on_failure()
+                } else {
+                    self.now().saturating_add(1)
+                }
+            }
+            ClockStrategy::Sharded { .. } => {
+                let wts = self.now().saturating_add(1);
+                self.local().fetch_max(wts, Ordering::SeqCst);
+                wts
+            }
+        };
+
+        // Lazy clocks don't move on commits, the next commit of a variable could take the same
+        // timestamp otherwise. Versions of a variable are told apart by their timestamps.
+        wts.max(latest.saturating_add(1))
+    }
+
+    fn increment(&self) -> u64 {
+        self.clock()
+            .fetch_add(1, Ordering::SeqCst)
+            .saturating_add(1)
+    }
+
+    fn pass_on_failure(&self) -> u64 {
+        let clock = self.clock();
+        let now = clock.load(Ordering::SeqCst);
+
+        match clock.compare_exchange(now, now + 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => now + 1,
+            // Some other commit has moved the clock past the published versions.
+            Err(current) => current,
+        }
+    }
+
+    fn clock(&self) -> &AtomicU64 {
+        &self.shards[0].0
+    }
+
+    ///
+    /// Shard of the current thread.
+    fn local(&self) -> &AtomicU64 {
+        let shard = LOCAL_SHARD.with(|s| *s) % self.shards.len();
+        &self.shards[shard].0
+    }
+
+    ///
+    /// Dispenses a new TVar id.
+    pub(crate) fn dispense_tvar_id(&self) -> u64 {
+        self.tvars.fetch_add(1, Ordering::SeqCst).saturating_add(1)
+    }
+
+    ///
+    /// Held by the commits while they take a write timestamp and publish their versions.
+    ///
+    /// Commits only claim a slot of their own, snapshots wait for the slots that are held when
+    /// they start, see [Clock::snapshot].
+    pub(crate) fn publishing(&self) -> Publishing<'_> {
+        let start = LOCAL_SHARD.with(|s| *s);
+
+        for i in (start..).map(|i| i % PUBLISHING_SLOTS) {
+            let slot = &self.publishing[i].0;
+            let seq = slot.load(Ordering::SeqCst);
+
+            if seq % 2 == 0
+                && slot
+                    .compare_exchange(seq, seq + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                return Publishing(slot);
+            }
+
+            // Every slot is held, some commit frees one shortly.
+            if i + 1 == start % PUBLISHING_SLOTS {
+                thread::yield_now();
+            }
+        }
+
+        unreachable!("Slots are probed endlessly.")
+    }
+
+    ///
+    /// Starts a snapshot read, and returns its read timestamp. Read timestamp is at least the
+    /// given one, since lazy clocks may lag behind the commits of the current thread.
+    ///
+    /// Versions visible at the read timestamp are retained until [Clock::release] is called
+    /// with it, and every version stamped at or before it is published once this returns.
+    pub(crate) fn snapshot(&self, ts: u64) -> u64 {
+        let rts = {
+            let mut readers = self.readers.lock();
+
+            // Commits that take their write timestamp from now on keep every version until the
+            // snapshot is registered, so the ones visible at it can't be collected meanwhile.
+            self.horizon.store(0, Ordering::SeqCst);
+            let rts = self.advance(ts);
+            *readers.entry(rts).or_insert(0) += 1;
+            self.set_horizon(&readers);
+
+            rts
+        };
+
+        // Commits which might have taken a write timestamp before are waited for to publish.
+        for slot in self.publishing.iter() {
+            let seq = slot.0.load(Ordering::SeqCst);
+            while seq % 2 != 0 && slot.0.load(Ordering::SeqCst) == seq {
+                thread::yield_now();
+            }
+        }
+
+        rts
+    }
+
+    ///
+    /// Ends a snapshot read started with [Clock::snapshot] at the given read timestamp.
+    pub(crate) fn release(&self, rts: u64) {
+        let mut readers = self.readers.lock();
+        if let Some(count) = readers.get_mut(&rts) {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&rts);
+            }
+        }
+
+        self.set_horizon(&readers);
+    }
+
+    fn set_horizon(&self, readers: &BTreeMap<u64, usize>) {
+        let horizon = readers.keys().next().copied().unwrap_or(u64::MAX);
+        self.horizon.store(horizon, Ordering::SeqCst);
+    }
+
+    ///
+    /// Oldest read timestamp that snapshot reads are ongoing at. Versions that are older than
+    /// the ones visible at it are not visible to anyone.
+    pub(crate) fn horizon(&self) -> u64 {
+        self.horizon.load(Ordering::SeqCst)
+    }
+}
+
+///
+/// Publishing slot of a commit, released when it is dropped.
+pub(crate) struct Publishing<'a>(&'a AtomicU64);
+
+impl Drop for Publishing<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    #[test]
+    fn clock_strategies() {
+        let gv1 = Clock::new(ClockStrategy::Gv1);
+        assert_eq!((gv1.tick(0), gv1.tick(0), gv1.now()), (1, 2, 2));
+
+        let gv4 = Clock::new(ClockStrategy::Gv4);
+        assert_eq!((gv4.tick(0), gv4.tick(0), gv4.now()), (1, 2, 2));
+
+        // Lazy clock moves only when it is advanced.
+        let gv5 = Clock::new(ClockStrategy::Gv5);
+        assert_eq!((gv5.tick(0), gv5.tick(0), gv5.now()), (1, 1, 0));
+        assert_eq!(gv5.advance(1), 1);
+        assert_eq!((gv5.tick(0), gv5.now()), (2, 1));
+
+        // Commits of the same variable are stamped apart even if the clock lags behind.
+        assert_eq!((gv5.tick(2), gv5.tick(3), gv5.now()), (3, 4, 1));
+
+        let gv6 = Clock::new(ClockStrategy::Gv6 { period: 2 });
+        let ticks: Vec<u64> = (0..4).map(|_| gv6.tick(0)).collect();
+        assert!(ticks.windows(2).all(|w| w[0] <= w[1]));
+        assert!(gv6.now() >= 1);
+
+        let sharded = Clock::new(ClockStrategy::Sharded { shards: 4 });
+        assert_eq!((sharded.tick(0), sharded.tick(0), sharded.now()), (1, 2, 2));
+        assert_eq!(sharded.advance(10), 10);
+    }
+
+    #[test]
+    fn clock_snapshots_wait_for_publishing_commits() {
+        let clock = Arc::new(Clock::default());
+        assert_eq!(clock.horizon(), u64::MAX);
+
+        let rts = clock.snapshot(0);
+        assert_eq!((rts, clock.horizon()), (0, 0));
+
+        let (stamped, published) = (Arc::new(Barrier::new(2)), Arc::new(AtomicBool::new(false)));
+        let commit = {
+            let (clock, stamped, published) = (clock.clone(), stamped.clone(), published.clone());
+            thread::spawn(move || {
+                let _publishing = clock.publishing();
+                let wts = clock.tick(0);
+                stamped.wait();
+
+                thread::sleep(Duration::from_millis(50));
+                published.store(true, Ordering::SeqCst);
+                wts
+            })
+        };
+
+        stamped.wait();
+        assert_eq!(clock.snapshot(0), 1);
+        assert!(published.load(Ordering::SeqCst));
+        assert_eq!(commit.join().unwrap(), 1);
+
+        // Horizon is the oldest ongoing snapshot.
+        clock.release(rts);
+        assert_eq!(clock.horizon(), 1);
+        clock.release(1);
+        assert_eq!(clock.horizon(), u64::MAX);
+    }
+}
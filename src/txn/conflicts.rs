@@ -1,4 +1,4 @@
-use super::transact::TransactionIsolation;
+use super::transact::{TransactionIsolation, Txn};
 use crate::txn::readset::ReadSet;
 use crate::txn::writeset::WriteSet;
 use std::cmp::Ordering;
@@ -35,28 +35,27 @@ impl PartialOrd for Compare {
 pub(crate) struct ConflictManager;
 
 impl ConflictManager {
-    pub(crate) fn check<T: 'static + Clone + Sync + Send>(iso: &TransactionIsolation) -> bool {
-        match iso {
-            // Serializable is also a checking for write conflicts, seprated from serializable reads only mode.
-            // Serializable will do check for write conflicts too. Even that would never happen.
+    pub(crate) fn check(txn: &Txn) -> bool {
+        match txn.iso {
+            // Serializable Snapshot Isolation, writes are conflicting if the variable is committed
+            // by someone else after the snapshot is taken. Reads are conflicting only if they
+            // form a dangerous structure of rw-antidependencies with the concurrent transactions.
             TransactionIsolation::Serializable => {
-                let rs = ReadSet::local();
                 let ws = WriteSet::local();
+                let writes = ws.cmps();
 
-                let mut linear = rs.cmps::<T>();
-                let _writes_before_rev: Vec<Compare>;
-
-                let pinned_rev = rs.first::<T>().checked_add(1).unwrap_or(u64::MAX);
-                let writes_before_rev = ws.writes_before::<T>(pinned_rev);
-                linear.extend(writes_before_rev);
-
-                // dbg!(&linear);
-
-                linear.iter().all(|x| x.current)
+                writes.iter().all(|x| x.current)
+                    && txn.ssi.try_commit(&txn.commits.ssi, ws.readers())
+            }
+            // Snapshot Isolation, only the writes are conflicting, first committer wins.
+            TransactionIsolation::SnapshotIsolation => {
+                let ws = WriteSet::local();
+                let writes = ws.cmps();
+                writes.iter().all(|x| x.current)
             }
             TransactionIsolation::RepeatableRead => {
                 let rs = ReadSet::local();
-                let cmps = rs.cmps::<T>();
+                let cmps = rs.cmps();
                 cmps.iter().all(|x| x.current)
             }
             TransactionIsolation::ReadCommitted => true,
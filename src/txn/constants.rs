@@ -5,3 +5,6 @@ pub(crate) const DEFAULT_TX_SERIALIZABLE_ENABLED: bool = false;
 pub(crate) const DEFAULT_TX_CONCURRENCY: TransactionConcurrency =
     TransactionConcurrency::Pessimistic;
 pub(crate) const DEFAULT_TX_ISOLATION: TransactionIsolation = TransactionIsolation::RepeatableRead;
+pub(crate) const DEFAULT_TX_MAX_VERSIONS: usize = 8_usize;
+pub(crate) const DEFAULT_TMAP_BUCKETS: usize = 64_usize;
+pub(crate) const DEFAULT_WATCH_CAPACITY: usize = 1024_usize;
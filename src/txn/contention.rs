@@ -0,0 +1,488 @@
+use crate::sync::atomics::AtomicBox;
+use crate::txn::transact::TransactionState;
+
+use lazy_static::*;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Birth order of the transactions, older transactions have smaller timestamps.
+    static ref BIRTHS: AtomicU64 = AtomicU64::new(0);
+}
+
+///
+/// Decision of a [ContentionManager] about a conflict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    ///
+    /// Waits for the given duration, then asks the contention manager again if the conflict
+    /// still exists. When there is no enemy, the transaction is executed again after waiting.
+    Wait(Duration),
+    ///
+    /// Aborts the ongoing execution of the transaction and executes it again.
+    Retry,
+    ///
+    /// Rolls back the enemy transaction and waits for it to release what it holds.
+    /// When there is no enemy, it is the same with [Resolution::Retry].
+    AbortEnemy,
+}
+
+///
+/// Policy that decides how a transaction proceeds when it conflicts with another one.
+///
+/// Contention manager is asked when a transaction can't acquire the lock of a variable because
+/// another transaction, the enemy, holds it. It is asked without an enemy when an execution of
+/// an optimistic transaction is aborted, before it is executed again.
+pub trait ContentionManager: Send + Sync {
+    ///
+    /// Resolves the conflict of `me` with `enemy`.
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution;
+}
+
+///
+/// Contention bookkeeping of a transaction, shared by all executions of it.
+pub struct Contender {
+    /// Birth timestamp of the transaction
+    birth: u64,
+    /// Instant the first execution has started at
+    started: Instant,
+    /// Number of executions started so far
+    attempts: AtomicU64,
+    /// Number of variables opened by all executions so far
+    karma: AtomicU64,
+    /// Number of times the contention manager is asked for the ongoing conflict
+    conflicts: AtomicU64,
+    /// Transaction is waiting for an enemy
+    waiting: AtomicBool,
+    /// State of the ongoing execution
+    execution: Mutex<Arc<AtomicBox<TransactionState>>>,
+    /// Transaction that the ongoing execution waits for
+    waits_for: Mutex<Option<Arc<Contender>>>,
+    /// Why the ongoing execution is rolled back by the contention manager
+    reason: Mutex<Option<String>>,
+}
+
+impl Contender {
+    pub(crate) fn new() -> Self {
+        Self {
+            birth: BIRTHS.fetch_add(1, Ordering::SeqCst),
+            started: Instant::now(),
+            attempts: AtomicU64::default(),
+            karma: AtomicU64::default(),
+            conflicts: AtomicU64::default(),
+            waiting: AtomicBool::default(),
+            execution: Mutex::new(Arc::new(AtomicBox::new(TransactionState::default()))),
+            waits_for: Mutex::new(None),
+            reason: Mutex::new(None),
+        }
+    }
+
+    ///
+    /// Birth timestamp of the transaction. Transactions that are born earlier have smaller
+    /// timestamps, retries don't change it.
+    pub fn birth(&self) -> u64 {
+        self.birth
+    }
+
+    ///
+    /// Instant the first execution of the transaction has started at.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    ///
+    /// Time passed since the first execution of the transaction.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    ///
+    /// Number of executions started so far, including the ongoing one.
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Amount of work done by the transaction, number of variables opened by all of its executions.
+    pub fn karma(&self) -> u64 {
+        self.karma.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Number of times the contention manager is asked for the ongoing conflict, including the
+    /// ongoing call.
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Is transaction waiting for an enemy.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn attempt(&self, execution: Arc<AtomicBox<TransactionState>>) {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        *self.execution.lock() = execution;
+        *self.reason.lock() = None;
+    }
+
+    pub(crate) fn opened(&self) {
+        self.karma.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn conflicted(&self) {
+        self.conflicts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn wait(&self, waiting: bool) {
+        self.waiting.store(waiting, Ordering::SeqCst);
+        if !waiting {
+            self.conflicts.store(0, Ordering::SeqCst);
+            *self.waits_for.lock() = None;
+        }
+    }
+
+    ///
+    /// Transaction that the ongoing execution waits for, if it is waiting.
+    pub(crate) fn waits_for(&self) -> Option<Arc<Contender>> {
+        self.waits_for.lock().clone()
+    }
+
+    pub(crate) fn wait_for(&self, enemy: Arc<Contender>) {
+        *self.waits_for.lock() = Some(enemy);
+    }
+
+    ///
+    /// Rolls back the ongoing execution, if it is still active. Returns false if it is not.
+    pub(crate) fn abort(&self) -> bool {
+        let mut aborted = false;
+        self.execution.lock().replace_with(|s| match *s {
+            TransactionState::Active => {
+                aborted = true;
+                TransactionState::MarkedRollback
+            }
+            ref s => s.clone(),
+        });
+
+        aborted
+    }
+
+    ///
+    /// Rolls back the ongoing execution for the given reason, if it is still active.
+    pub(crate) fn abort_with(&self, reason: String) {
+        if self.abort() {
+            *self.reason.lock() = Some(reason);
+        }
+    }
+
+    ///
+    /// Why the ongoing execution is rolled back by the contention manager, if it is.
+    pub(crate) fn abort_reason(&self) -> Option<String> {
+        self.reason.lock().clone()
+    }
+}
+
+///
+/// Exponential backoff.
+///
+/// Waits for the enemy and before the re-executions, doubling the wait time at every attempt.
+/// Never aborts the enemy.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Wait time of the first attempt
+    pub min: Duration,
+    /// Upper bound of the wait time
+    pub max: Duration,
+}
+
+impl Backoff {
+    fn wait(&self, n: u64) -> Duration {
+        let factor = 1_u32.checked_shl(n.min(31) as u32).unwrap_or(u32::MAX);
+        self.min.saturating_mul(factor).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            min: Duration::from_micros(1),
+            max: Duration::from_millis(10),
+        }
+    }
+}
+
+impl ContentionManager for Backoff {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(_) => Resolution::Wait(self.wait(me.conflicts())),
+            None => Resolution::Wait(self.wait(me.attempts())),
+        }
+    }
+}
+
+///
+/// Karma.
+///
+/// Transaction that has done more work wins. Loser waits for fixed intervals, and each
+/// interval adds to its priority until it overcomes the enemy. Work of the aborted executions
+/// is kept in the karma, so the long transactions are not starved.
+#[derive(Debug, Clone)]
+pub struct Karma {
+    /// Wait interval of the loser
+    pub interval: Duration,
+}
+
+impl Default for Karma {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl ContentionManager for Karma {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.karma() + me.conflicts() > enemy.karma() => Resolution::AbortEnemy,
+            Some(_) => Resolution::Wait(self.interval),
+            None => Resolution::Retry,
+        }
+    }
+}
+
+///
+/// Polka.
+///
+/// [Karma] with exponential backoff. Loser waits for the enemy as many times as the difference
+/// of their karma, doubling the wait time at every interval, then aborts the enemy.
+#[derive(Debug, Clone, Default)]
+pub struct Polka {
+    /// Backoff between the intervals and the re-executions
+    pub backoff: Backoff,
+}
+
+impl ContentionManager for Polka {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.karma() + me.conflicts() > enemy.karma() => Resolution::AbortEnemy,
+            Some(_) => Resolution::Wait(self.backoff.wait(me.conflicts())),
+            None => Resolution::Wait(self.backoff.wait(me.attempts())),
+        }
+    }
+}
+
+///
+/// Greedy.
+///
+/// Older transaction wins, and the enemy that is waiting for some other transaction is aborted
+/// regardless of its age. Every transaction commits in bounded time.
+#[derive(Debug, Clone)]
+pub struct Greedy {
+    /// Wait interval of the younger transaction
+    pub interval: Duration,
+}
+
+impl Default for Greedy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl ContentionManager for Greedy {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.birth() < enemy.birth() || enemy.is_waiting() => {
+                Resolution::AbortEnemy
+            }
+            Some(_) => Resolution::Wait(self.interval),
+            None => Resolution::Retry,
+        }
+    }
+}
+
+///
+/// Timestamp priority.
+///
+/// Older transaction aborts the younger enemy, younger transaction gives up its execution
+/// instead of waiting for the older one.
+#[derive(Debug, Clone, Default)]
+pub struct Timestamp;
+
+impl ContentionManager for Timestamp {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.birth() < enemy.birth() => Resolution::AbortEnemy,
+            _ => Resolution::Retry,
+        }
+    }
+}
+
+///
+/// Wait-die.
+///
+/// Older transaction waits for the younger enemy, younger transaction is rolled back instead of
+/// waiting for the older one. Waits only go from the older to the younger transactions, so they
+/// never deadlock.
+#[derive(Debug, Clone)]
+pub struct WaitDie {
+    /// Wait interval of the older transaction
+    pub interval: Duration,
+}
+
+impl Default for WaitDie {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl ContentionManager for WaitDie {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.birth() < enemy.birth() => Resolution::Wait(self.interval),
+            Some(enemy) => {
+                me.abort_with(format!(
+                    "died instead of waiting for the older txn #{}",
+                    enemy.birth()
+                ));
+                Resolution::Retry
+            }
+            None => Resolution::Retry,
+        }
+    }
+}
+
+///
+/// Wound-wait.
+///
+/// Older transaction rolls back the younger enemy, younger transaction waits for the older one.
+/// Waits only go from the younger to the older transactions, so they never deadlock.
+#[derive(Debug, Clone)]
+pub struct WoundWait {
+    /// Wait interval of the younger transaction
+    pub interval: Duration,
+}
+
+impl Default for WoundWait {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl ContentionManager for WoundWait {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        match enemy {
+            Some(enemy) if me.birth() < enemy.birth() => {
+                enemy.abort_with(format!("was wounded by the older txn #{}", me.birth()));
+                Resolution::Wait(self.interval)
+            }
+            Some(_) => Resolution::Wait(self.interval),
+            None => Resolution::Retry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod contention_tests {
+    use super::*;
+
+    fn contender(karma: u64) -> Contender {
+        let c = Contender::new();
+        (0..karma).for_each(|_| c.opened());
+        c
+    }
+
+    #[test]
+    fn contention_backoff() {
+        let cm = Backoff::default();
+        let me = contender(0);
+
+        me.attempt(Arc::new(AtomicBox::new(TransactionState::Active)));
+        assert_eq!(
+            cm.resolve(&me, None),
+            Resolution::Wait(Duration::from_micros(2))
+        );
+
+        (0..20).for_each(|_| me.conflicted());
+        assert_eq!(
+            cm.resolve(&me, Some(&contender(0))),
+            Resolution::Wait(Duration::from_millis(10))
+        );
+    }
+
+    #[test]
+    fn contention_karma_and_polka() {
+        let me = contender(1);
+        let enemy = contender(3);
+
+        assert_eq!(
+            Karma::default().resolve(&me, Some(&enemy)),
+            Resolution::Wait(Duration::from_micros(100))
+        );
+        assert!(matches!(
+            Polka::default().resolve(&me, Some(&enemy)),
+            Resolution::Wait(_)
+        ));
+
+        // Waiting adds to the priority.
+        (0..3).for_each(|_| me.conflicted());
+        assert_eq!(
+            Karma::default().resolve(&me, Some(&enemy)),
+            Resolution::AbortEnemy
+        );
+        assert_eq!(
+            Polka::default().resolve(&me, Some(&enemy)),
+            Resolution::AbortEnemy
+        );
+    }
+
+    #[test]
+    fn contention_greedy_and_timestamp() {
+        let older = contender(0);
+        let younger = contender(0);
+
+        assert_eq!(
+            Greedy::default().resolve(&older, Some(&younger)),
+            Resolution::AbortEnemy
+        );
+        assert!(matches!(
+            Greedy::default().resolve(&younger, Some(&older)),
+            Resolution::Wait(_)
+        ));
+
+        older.wait(true);
+        assert_eq!(
+            Greedy::default().resolve(&younger, Some(&older)),
+            Resolution::AbortEnemy
+        );
+
+        assert_eq!(
+            Timestamp.resolve(&older, Some(&younger)),
+            Resolution::AbortEnemy
+        );
+        assert_eq!(Timestamp.resolve(&younger, Some(&older)), Resolution::Retry);
+    }
+
+    #[test]
+    fn contention_abort_enemy() {
+        let enemy = contender(0);
+        let execution = Arc::new(AtomicBox::new(TransactionState::Active));
+        enemy.attempt(execution.clone());
+
+        assert!(enemy.abort());
+        assert!(matches!(*execution.get(), TransactionState::MarkedRollback));
+
+        // Finished executions are not touched.
+        execution.replace_with(|_| TransactionState::Committed);
+        assert!(!enemy.abort());
+        assert!(matches!(*execution.get(), TransactionState::Committed));
+    }
+}
@@ -0,0 +1,749 @@
+use crate::txn::errors::*;
+use crate::txn::lockset::{Held, LockSet};
+use crate::txn::readset::ReadSet;
+use crate::txn::transact::{TransactionState, Txn, TxnManager};
+use crate::txn::version::Entry;
+use crate::txn::writeset::WriteSet;
+
+use lazy_static::*;
+use parking_lot::{Condvar, Mutex};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Timer of the delays, shared by all of them
+    static ref TIMER: Arc<Timer> = Timer::start();
+}
+
+///
+/// Thread-local state of a transaction execution.
+#[derive(Default)]
+struct Locals {
+    txn: Txn,
+    rts: u64,
+    snapshot: Option<u64>,
+    reads: HashMap<u64, Entry>,
+    writes: HashMap<u64, Entry>,
+    locks: HashMap<u64, Held>,
+}
+
+impl Locals {
+    ///
+    /// Exchanges this state with the state of the current thread.
+    fn swap(&mut self) {
+        Txn::swap_local(&mut self.txn);
+        TxnManager::swap_local(&mut self.rts, &mut self.snapshot);
+        ReadSet::swap_local(&mut self.reads);
+        WriteSet::swap_local(&mut self.writes);
+        LockSet::swap_local(&mut self.locks);
+    }
+}
+
+///
+/// Runs the given closure out of the ongoing transaction of the current thread, which is
+/// resumed afterwards.
+pub(crate) fn detached<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let mut locals = Locals::default();
+    locals.swap();
+    let res = f();
+    locals.swap();
+    res
+}
+
+///
+/// Execution of an optimistic transaction which is detached from any thread.
+///
+/// Context is started with [Txn::start] and owns the read and write sets of the execution, so
+/// it can be suspended on one thread and resumed on another. Context is resumed on the current
+/// thread with [TxnContext::resume] and finished with [TxnContext::commit] or
+/// [TxnContext::rollback]. Dropping an unfinished context rolls it back.
+///
+/// Detached executions are never executed again. If an execution is rolled back because of a
+/// conflict, it is finished with [TxnError::RetryWithContext] and it is up to the caller to
+/// start it over.
+pub struct TxnContext {
+    /// State of the execution while it is suspended, state of the thread while it is resumed
+    locals: Locals,
+    /// Execution is committed or rolled back
+    finished: bool,
+}
+
+impl TxnContext {
+    pub(crate) fn start(txn: Txn) -> TxnResult<Self> {
+        let mut ctx = Self {
+            locals: Locals {
+                txn,
+                ..Locals::default()
+            },
+            finished: false,
+        };
+
+        ctx.check_thread()?;
+        ctx.locals.swap();
+        Txn::get_local().on_start();
+        ctx.suspend();
+
+        Ok(ctx)
+    }
+
+    ///
+    /// Transaction of the execution. It can be rolled back or marked rollback-only from any
+    /// thread, even while it is suspended.
+    pub fn txn(&self) -> &Txn {
+        &self.locals.txn
+    }
+
+    ///
+    /// Is the execution committed or rolled back.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    ///
+    /// Resumes the execution on the current thread, runs the given closure in it and suspends
+    /// it again.
+    ///
+    /// If the execution is rolled back meanwhile, it is finished and [TxnError::Abort] is
+    /// returned when it is rolled back explicitly, [TxnError::RetryWithContext] otherwise.
+    pub fn resume<F, R>(&mut self, f: F) -> TxnResult<R>
+    where
+        F: FnOnce(&mut Txn) -> R,
+    {
+        self.resumed()?;
+
+        // Execution is detached if the closure panics, so the thread doesn't carry on in it
+        // and dropping the context still rolls it back.
+        let attached = Attachment(self);
+        let mut me = Txn::get_local();
+        let res = f(&mut me);
+        std::mem::forget(attached);
+
+        if me.is_active() {
+            self.suspend();
+            Ok(res)
+        } else {
+            Err(self.fail(&me, "execution is rolled back"))
+        }
+    }
+
+    ///
+    /// Runs the given future in the execution. Execution is resumed on the polling thread
+    /// while the future is polled and suspended in between, so the future can await and move
+    /// between threads.
+    ///
+    /// Output is the same with [TxnContext::resume] once the future completes.
+    pub fn run<Fut>(&mut self, fut: Fut) -> Attached<'_, Fut>
+    where
+        Fut: Future,
+    {
+        Attached {
+            ctx: self,
+            fut: Box::pin(fut),
+        }
+    }
+
+    ///
+    /// Validates and commits the execution on the current thread.
+    pub fn commit(mut self) -> TxnResult<()> {
+        self.resumed()?;
+
+        let mut me = Txn::get_local();
+        let res = match me.on_validate() {
+            Ok(()) => me.commit(),
+            Err(reason) => Err(TxnError::RetryWithContext(reason.into())),
+        };
+
+        match res {
+            Ok(()) => {
+                self.finish();
+                Ok(())
+            }
+            Err(e) if me.is_committed() => {
+                self.finish();
+                Err(e)
+            }
+            Err(TxnError::RetryWithContext(reason)) => Err(self.fail(&me, &reason)),
+            Err(e) => {
+                self.fail(&me, "");
+                Err(e)
+            }
+        }
+    }
+
+    ///
+    /// Rolls back the execution.
+    pub fn rollback(mut self) {
+        self.abort();
+    }
+
+    ///
+    /// Rolls back the execution without checking the thread, the state of the current thread
+    /// is restored afterwards.
+    fn abort(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        self.locals.swap();
+        let me = Txn::get_local();
+        me.on_abort();
+        me.rolled_back();
+        self.finish();
+    }
+
+    fn check_thread(&self) -> TxnResult<()> {
+        if self.finished {
+            return Err(TxnError::AbortWithContext(
+                "Transaction context is finished".into(),
+            ));
+        }
+
+        if Txn::get_local().is_active() {
+            return Err(TxnError::AbortWithContext(
+                "Thread is in a transaction already".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Attaches the execution to the current thread.
+    fn resumed(&mut self) -> TxnResult<()> {
+        self.check_thread()?;
+        self.locals.swap();
+
+        let me = Txn::get_local();
+        me.state.replace_with(|s| match *s {
+            TransactionState::Suspended if me.is_rollback_only() => {
+                TransactionState::MarkedRollback
+            }
+            TransactionState::Suspended => TransactionState::Active,
+            ref s => s.clone(),
+        });
+        me.track();
+
+        Ok(())
+    }
+
+    ///
+    /// Detaches the execution from the current thread.
+    fn suspend(&mut self) {
+        Txn::get_local().state.replace_with(|s| match *s {
+            TransactionState::Active => TransactionState::Suspended,
+            ref s => s.clone(),
+        });
+        self.locals.swap();
+    }
+
+    ///
+    /// Rolls back the attached execution, and detaches it for the last time.
+    fn fail(&mut self, me: &Txn, reason: &str) -> TxnError {
+        me.on_abort();
+        me.rolled_back();
+        self.finish();
+
+        if me.is_rollback_only() {
+            TxnError::Abort
+        } else {
+            TxnError::RetryWithContext(reason.into())
+        }
+    }
+
+    fn finish(&mut self) {
+        self.locals.swap();
+        self.locals.txn.untrack();
+        self.finished = true;
+    }
+}
+
+impl Drop for TxnContext {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+///
+/// Execution which is resumed on the current thread, it is suspended again when this is
+/// dropped.
+struct Attachment<'a>(&'a mut TxnContext);
+
+impl<'a> Drop for Attachment<'a> {
+    fn drop(&mut self) {
+        self.0.suspend();
+    }
+}
+
+///
+/// Future running in a detached execution, see [TxnContext::run].
+pub struct Attached<'a, Fut> {
+    ctx: &'a mut TxnContext,
+    fut: Pin<Box<Fut>>,
+}
+
+impl<'a, Fut> Future for Attached<'a, Fut>
+where
+    Fut: Future,
+{
+    type Output = TxnResult<Fut::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { ctx, fut } = self.get_mut();
+
+        match ctx.resume(|_| fut.as_mut().poll(cx)) {
+            Ok(Poll::Ready(res)) => Poll::Ready(Ok(res)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+///
+/// Future which completes after the given duration, without blocking the polling thread.
+///
+/// Delays are woken by a single timer thread, so they work under any executor.
+pub(crate) struct Delay {
+    wait: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Delay {
+    pub(crate) fn new(wait: Duration) -> Self {
+        Self {
+            wait,
+            deadline: None,
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let wait = self.wait;
+        let deadline = *self.deadline.get_or_insert_with(|| Instant::now() + wait);
+
+        if Instant::now() >= deadline {
+            return Poll::Ready(());
+        }
+
+        TIMER.wake_at(deadline, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+///
+/// Deadline of a pending delay and the waker of its task.
+struct Alarm {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for Alarm {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Alarm {}
+
+impl PartialOrd for Alarm {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Alarm {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+///
+/// Timer thread, which wakes the tasks of the delays once their deadlines pass.
+struct Timer {
+    /// Alarms which are yet to go off, earliest first
+    alarms: Mutex<BinaryHeap<Reverse<Alarm>>>,
+    /// Wakes up the timer thread when an alarm is set
+    set: Condvar,
+}
+
+impl Timer {
+    fn start() -> Arc<Self> {
+        let timer = Arc::new(Self {
+            alarms: Mutex::new(BinaryHeap::new()),
+            set: Condvar::new(),
+        });
+
+        let t = timer.clone();
+        thread::Builder::new()
+            .name("lever_timer".into())
+            .spawn(move || t.run())
+            .expect("timer thread couldn't be started");
+
+        timer
+    }
+
+    ///
+    /// Wakes the given waker once the deadline passes. Task may be woken more than once if it
+    /// is polled before the deadline again.
+    fn wake_at(&self, deadline: Instant, waker: Waker) {
+        let mut alarms = self.alarms.lock();
+        let earliest = alarms.peek().is_none_or(|Reverse(a)| deadline < a.deadline);
+        alarms.push(Reverse(Alarm { deadline, waker }));
+
+        if earliest {
+            self.set.notify_one();
+        }
+    }
+
+    fn run(&self) {
+        let mut alarms = self.alarms.lock();
+
+        loop {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+
+            while let Some(Reverse(alarm)) = alarms.peek() {
+                if alarm.deadline > now {
+                    break;
+                }
+                if let Some(Reverse(alarm)) = alarms.pop() {
+                    expired.push(alarm.waker);
+                }
+            }
+
+            // Tasks are woken without the lock, so wakers which poll inline can set alarms.
+            if !expired.is_empty() {
+                drop(alarms);
+                expired.into_iter().for_each(Waker::wake);
+                alarms = self.alarms.lock();
+                continue;
+            }
+
+            match alarms.peek() {
+                Some(Reverse(alarm)) => {
+                    let deadline = alarm.deadline;
+                    self.set.wait_until(&mut alarms, deadline);
+                }
+                None => self.set.wait(&mut alarms),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod context_tests {
+    use super::Delay;
+    use crate::txn::errors::*;
+    use crate::txn::fixture::txn;
+    use crate::txn::prelude::*;
+    use std::future::Future;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn context_across_threads() {
+        let tvar = TVar::new(100_u64);
+        let mut ctx = txn().start().unwrap();
+
+        let read = ctx.resume(|t| t.read(&tvar)).unwrap();
+        assert_eq!(read, 100);
+
+        let mut var = tvar.clone();
+        let ctx = thread::spawn(move || {
+            ctx.resume(|t| {
+                let v = t.read(&var);
+                t.write(&mut var, v + 1);
+            })
+            .unwrap();
+            ctx
+        })
+        .join()
+        .unwrap();
+
+        // Writes are not visible until the context commits.
+        assert_eq!(tvar.get_data(), 100);
+
+        thread::spawn(move || ctx.commit()).join().unwrap().unwrap();
+        assert_eq!(tvar.get_data(), 101);
+    }
+
+    #[test]
+    fn context_conflict() {
+        let mut tvar = TVar::new(100_u64);
+        let mut ctx = txn().start().unwrap();
+
+        let mut var = tvar.clone();
+        ctx.resume(|t| {
+            let v = t.read(&var);
+            t.write(&mut var, v + 1);
+        })
+        .unwrap();
+
+        txn().begin(|t| t.write(&mut tvar, 200)).unwrap();
+
+        let res = thread::spawn(move || ctx.commit()).join().unwrap();
+        assert!(matches!(res, Err(TxnError::RetryWithContext(_))));
+        assert_eq!(tvar.get_data(), 200);
+    }
+
+    #[test]
+    fn context_rollback() {
+        let tvar = TVar::new(100_u64);
+
+        let mut ctx = txn().start().unwrap();
+        let mut var = tvar.clone();
+        ctx.resume(|t| t.write(&mut var, 200)).unwrap();
+
+        // Suspended executions can be rolled back from any thread.
+        let t = ctx.txn().clone();
+        thread::spawn(move || t.rollback()).join().unwrap();
+
+        let res = ctx.resume(|t| t.read(&var));
+        assert!(matches!(res, Err(TxnError::Abort)));
+        assert!(ctx.is_finished());
+        assert!(ctx.commit().is_err());
+
+        // Dropped contexts are rolled back.
+        let mut ctx = txn().start().unwrap();
+        let mut var = tvar.clone();
+        ctx.resume(|t| t.write(&mut var, 300)).unwrap();
+        drop(ctx);
+
+        assert_eq!(tvar.get_data(), 100);
+        assert!(!Txn::get_local().is_active());
+    }
+
+    #[test]
+    fn context_in_transaction() {
+        let res = txn().begin(|_| txn().start().is_err());
+        assert!(res.unwrap());
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn poll<F: Future + Unpin>(fut: &mut F) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::new(Noop));
+        Pin::new(fut).poll(&mut Context::from_waker(&waker))
+    }
+
+    ///
+    /// Pending once, then ready.
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn context_async_across_threads() {
+        let tvar = TVar::new(100_u64);
+        let executions = Arc::new(AtomicUsize::new(0));
+
+        let mut fut = {
+            let (txn, tvar, executions) = (txn(), tvar.clone(), executions.clone());
+            Box::pin(async move {
+                txn.begin_async(|mut t| {
+                    let mut var = tvar.clone();
+                    let executions = executions.clone();
+
+                    async move {
+                        executions.fetch_add(1, Ordering::SeqCst);
+                        let v = t.read(&var);
+                        YieldNow(false).await;
+                        t.write(&mut var, v + 1);
+                        v
+                    }
+                })
+                .await
+            })
+        };
+
+        assert!(poll(&mut fut).is_pending());
+
+        // Thread is free for other transactions while the future is pending, and their writes
+        // conflict with the suspended execution.
+        assert!(!Txn::get_local().is_active());
+        let mut other = tvar.clone();
+        txn().begin(|t| t.write(&mut other, 200)).unwrap();
+
+        let res = thread::spawn(move || loop {
+            if let Poll::Ready(res) = poll(&mut fut) {
+                break res;
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(res.unwrap(), 200);
+        assert_eq!(tvar.get_data(), 201);
+        assert_eq!(executions.load(Ordering::SeqCst), 2);
+
+        let pessimistic = TxnManager::global().txn_build(
+            TransactionConcurrency::Pessimistic,
+            TransactionIsolation::RepeatableRead,
+            100_usize,
+            1_usize,
+            "context".into(),
+        );
+        let mut fut = Box::pin(pessimistic.begin_async(|_| async {}));
+        assert!(matches!(
+            poll(&mut fut),
+            Poll::Ready(Err(TxnError::AbortWithContext(_)))
+        ));
+    }
+
+    ///
+    /// Backs off for a millisecond every time it is asked.
+    struct Counting(Arc<AtomicUsize>);
+
+    impl ContentionManager for Counting {
+        fn resolve(&self, _me: &Contender, _enemy: Option<&Contender>) -> Resolution {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Resolution::Wait(Duration::from_millis(1))
+        }
+    }
+
+    #[test]
+    fn context_async_backs_off() {
+        let tvar = TVar::new(100_u64);
+        let resolved = Arc::new(AtomicUsize::new(0));
+
+        let mut backing_off = txn();
+        backing_off.set_contention_manager(Counting(resolved.clone()));
+
+        let mut fut = {
+            let tvar = tvar.clone();
+            Box::pin(async move {
+                backing_off
+                    .begin_async(|mut t| {
+                        let mut var = tvar.clone();
+
+                        async move {
+                            let v = t.read(&var);
+                            YieldNow(false).await;
+                            t.write(&mut var, v + 1);
+                        }
+                    })
+                    .await
+            })
+        };
+
+        assert!(poll(&mut fut).is_pending());
+
+        let mut other = tvar.clone();
+        txn().begin(|t| t.write(&mut other, 200)).unwrap();
+
+        // Conflicting execution waits for the contention manager before it is executed again.
+        let res = loop {
+            if let Poll::Ready(res) = poll(&mut fut) {
+                break res;
+            }
+        };
+
+        assert!(res.is_ok());
+        assert_eq!(tvar.get_data(), 201);
+        assert_eq!(resolved.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn context_panics() {
+        let tvar = TVar::new(100_u64);
+        let mut ctx = txn().start().unwrap();
+
+        let mut var = tvar.clone();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            ctx.resume(|t| {
+                t.write(&mut var, 200);
+                panic!("closure panics");
+            })
+        }));
+        assert!(res.is_err());
+
+        // Execution is suspended again, so the thread is free and the context can go on.
+        assert!(!Txn::get_local().is_active());
+        assert_eq!(ctx.resume(|t| t.read(&var)).unwrap(), 200);
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut fut = ctx.run(async { panic!("future panics") });
+            poll(&mut fut)
+        }));
+        assert!(res.is_err());
+        assert!(!Txn::get_local().is_active());
+
+        drop(ctx);
+        assert_eq!(tvar.get_data(), 100);
+        txn().begin(|t| t.write(&mut var, 300)).unwrap();
+        assert_eq!(tvar.get_data(), 300);
+    }
+
+    ///
+    /// Sends its index once it is woken.
+    struct Sending(usize, Mutex<mpsc::Sender<usize>>);
+
+    impl Wake for Sending {
+        fn wake(self: Arc<Self>) {
+            let _ = self.1.lock().unwrap().send(self.0);
+        }
+    }
+
+    #[test]
+    fn context_delays() {
+        let (tx, rx) = mpsc::channel();
+        let started = Instant::now();
+
+        // Latest delays are set first, so the timer is woken up for the earlier ones.
+        let mut delays = (0..50)
+            .rev()
+            .map(|i| {
+                let mut delay = Box::pin(Delay::new(Duration::from_millis(i as u64 * 2)));
+                let waker = Waker::from(Arc::new(Sending(i, Mutex::new(tx.clone()))));
+                let ready = delay.as_mut().poll(&mut Context::from_waker(&waker));
+                (i, delay, ready.is_ready())
+            })
+            .collect::<Vec<_>>();
+        delays.reverse();
+
+        // Only the delay without a wait is ready at once, the others are woken in order of
+        // their deadlines.
+        assert!(delays[0].2);
+        assert!(delays[1..].iter().all(|(_, _, ready)| !ready));
+
+        let woken = (1..50).map(|_| rx.recv().unwrap()).collect::<Vec<_>>();
+        assert_eq!(woken, (1..50).collect::<Vec<_>>());
+        assert!(started.elapsed() >= Duration::from_millis(98));
+
+        for (_, delay, _) in delays.iter_mut() {
+            assert!(poll(delay).is_ready());
+        }
+    }
+}
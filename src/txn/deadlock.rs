@@ -0,0 +1,213 @@
+use crate::txn::contention::{Contender, ContentionManager, Resolution};
+
+use itertools::Itertools;
+use std::iter;
+use std::sync::Arc;
+use std::time::Duration;
+
+///
+/// Transaction that is rolled back to break a deadlock.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Victim {
+    ///
+    /// Youngest transaction of the cycle.
+    #[default]
+    Youngest,
+    ///
+    /// Transaction of the cycle with the lowest priority, i.e. the one that has done the least
+    /// work so far. Ties are broken by rolling back the younger one.
+    LowestPriority,
+}
+
+///
+/// Wait-for graph deadlock detector.
+///
+/// Transactions wait for the enemies holding the locks they want. Every waiting transaction
+/// points to its enemy, and the edges form the wait-for graph of the transactions. Before
+/// waiting, a transaction follows the edges starting from its enemy, and if they lead back to
+/// itself, transactions on the way wait for each other forever. One of them is picked as the
+/// [Victim] and rolled back, so the rest proceed.
+///
+/// Pessimistic victims fail with [TxnError::AbortWithContext](crate::txn::errors::TxnError)
+/// describing the cycle. Optimistic transactions lock at commit in the order of the variable
+/// ids, so they never deadlock and are not rolled back by the detector.
+#[derive(Debug, Clone)]
+pub struct DeadlockDetector {
+    /// Transaction that is rolled back when a cycle is found
+    pub victim: Victim,
+    /// Wait interval between the lock attempts, which is also the detection interval
+    pub interval: Duration,
+}
+
+impl Default for DeadlockDetector {
+    fn default() -> Self {
+        Self {
+            victim: Victim::default(),
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl DeadlockDetector {
+    ///
+    /// Transactions that the enemy waits for, directly or not, if they lead back to `me`.
+    fn cycle(me: &Contender, enemy: &Contender) -> Option<Vec<Arc<Contender>>> {
+        let mut rest: Vec<Arc<Contender>> = Vec::new();
+        let mut next = enemy.waits_for();
+
+        while let Some(c) = next {
+            if c.birth() == me.birth() {
+                return Some(rest);
+            }
+
+            // Cycle that doesn't involve `me`, it is broken by one of its own transactions.
+            if c.birth() == enemy.birth() || rest.iter().any(|r| r.birth() == c.birth()) {
+                return None;
+            }
+
+            next = c.waits_for();
+            rest.push(c);
+        }
+
+        None
+    }
+
+    fn pick<'a>(&self, cycle: &[&'a Contender]) -> &'a Contender {
+        let victim = match self.victim {
+            Victim::Youngest => cycle.iter().max_by_key(|c| c.birth()),
+            Victim::LowestPriority => cycle
+                .iter()
+                .min_by_key(|c| (c.karma(), u64::MAX - c.birth())),
+        };
+
+        victim.expect("Cycle is not empty")
+    }
+}
+
+impl ContentionManager for DeadlockDetector {
+    fn resolve(&self, me: &Contender, enemy: Option<&Contender>) -> Resolution {
+        let enemy = match enemy {
+            Some(enemy) => enemy,
+            None => return Resolution::Retry,
+        };
+
+        let rest = match Self::cycle(me, enemy) {
+            Some(rest) => rest,
+            None => return Resolution::Wait(self.interval),
+        };
+
+        let cycle: Vec<&Contender> = iter::once(me)
+            .chain(iter::once(enemy))
+            .chain(rest.iter().map(|c| &**c))
+            .collect();
+        let victim = self.pick(&cycle);
+
+        victim.abort_with(format!(
+            "was aborted to break the deadlock {} -> #{}",
+            cycle.iter().map(|c| format!("#{}", c.birth())).join(" -> "),
+            me.birth()
+        ));
+
+        if victim.birth() == me.birth() {
+            Resolution::Retry
+        } else {
+            Resolution::Wait(self.interval)
+        }
+    }
+}
+
+#[cfg(test)]
+mod deadlock_tests {
+    use crate::txn::errors::*;
+    use crate::txn::prelude::*;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    ///
+    /// Two pessimistic transactions lock the same variables in the opposite order, returns the
+    /// failure reasons.
+    fn opposite_order<C>(cm: C) -> Vec<String>
+    where
+        C: ContentionManager + 'static,
+    {
+        let mut txn = TxnManager::global().txn_build(
+            TransactionConcurrency::Pessimistic,
+            TransactionIsolation::RepeatableRead,
+            10_000_usize,
+            1_usize,
+            "deadlock".into(),
+        );
+        txn.set_contention_manager(cm);
+
+        let x = TVar::new(0_u64);
+        let y = TVar::new(0_u64);
+        let locked = Arc::new(Barrier::new(2));
+        let started = Instant::now();
+
+        let threads: Vec<_> = [(x.clone(), y.clone()), (y.clone(), x.clone())]
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(n, (mut first, mut second))| {
+                let txn = txn.clone();
+                let locked = locked.clone();
+
+                thread::spawn(move || {
+                    txn.begin(|t| {
+                        t.write(&mut first, n as u64 + 1);
+                        locked.wait();
+                        t.write(&mut second, n as u64 + 1);
+                    })
+                })
+            })
+            .collect();
+
+        let results: Vec<TxnResult<()>> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        // Deadlock is broken long before the lock timeout.
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+
+        // Winner has written both variables.
+        assert_eq!(x.get_data(), y.get_data());
+
+        results
+            .into_iter()
+            .filter_map(|r| match r {
+                Err(TxnError::AbortWithContext(reason)) => Some(reason),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deadlock_detector() {
+        let reasons = opposite_order(DeadlockDetector::default());
+        assert_eq!(reasons.len(), 1);
+        assert!(
+            reasons[0].contains("to break the deadlock"),
+            "{}",
+            reasons[0]
+        );
+
+        let reasons = opposite_order(DeadlockDetector {
+            victim: Victim::LowestPriority,
+            ..DeadlockDetector::default()
+        });
+        assert!(
+            reasons[0].contains("to break the deadlock"),
+            "{}",
+            reasons[0]
+        );
+    }
+
+    #[test]
+    fn deadlock_wait_die_wound_wait() {
+        let reasons = opposite_order(WaitDie::default());
+        assert!(reasons[0].contains("died"), "{}", reasons[0]);
+
+        let reasons = opposite_order(WoundWait::default());
+        assert!(reasons[0].contains("wounded"), "{}", reasons[0]);
+    }
+}
@@ -0,0 +1,26 @@
+use crate::txn::transact::{TransactionConcurrency, TransactionIsolation, Txn, TxnManager};
+
+///
+/// Optimistic transaction of the given manager, with the given isolation and label, and the
+/// timeout the tests run with.
+pub(crate) fn txn_of(manager: &TxnManager, iso: TransactionIsolation, label: &str) -> Txn {
+    manager.txn_build(
+        TransactionConcurrency::Optimistic,
+        iso,
+        100_usize,
+        1_usize,
+        label.into(),
+    )
+}
+
+///
+/// Optimistic, repeatable read transaction of the given manager.
+pub(crate) fn txn_in(manager: &TxnManager) -> Txn {
+    txn_of(manager, TransactionIsolation::RepeatableRead, "test")
+}
+
+///
+/// Optimistic, repeatable read transaction of the default manager.
+pub(crate) fn txn() -> Txn {
+    txn_in(&TxnManager::global())
+}
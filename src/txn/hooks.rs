@@ -0,0 +1,61 @@
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+///
+/// Callbacks that are registered in an execution of a transaction, to run once it commits or
+/// rolls back for the last time.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks(Arc<Mutex<Registered>>);
+
+#[derive(Default)]
+struct Registered {
+    commit: Vec<Hook>,
+    rollback: Vec<Hook>,
+}
+
+impl Hooks {
+    pub(crate) fn on_commit(&self, hook: Hook) {
+        self.0.lock().commit.push(hook);
+    }
+
+    pub(crate) fn on_rollback(&self, hook: Hook) {
+        self.0.lock().rollback.push(hook);
+    }
+
+    ///
+    /// Number of the commit and rollback hooks registered so far.
+    pub(crate) fn mark(&self) -> (usize, usize) {
+        let hooks = self.0.lock();
+        (hooks.commit.len(), hooks.rollback.len())
+    }
+
+    ///
+    /// Discards the commit hooks registered after the given mark, and runs the rollback hooks
+    /// registered after it.
+    pub(crate) fn rollback_to(&self, (commit, rollback): (usize, usize)) {
+        let rolled_back = {
+            let mut hooks = self.0.lock();
+            let rollback = rollback.min(hooks.rollback.len());
+            hooks.commit.truncate(commit);
+            hooks.rollback.split_off(rollback)
+        };
+
+        rolled_back.into_iter().for_each(|hook| hook());
+    }
+
+    ///
+    /// Runs the commit hooks, rollback hooks are discarded.
+    pub(crate) fn committed(&self) {
+        let commit = std::mem::take(&mut *self.0.lock()).commit;
+        commit.into_iter().for_each(|hook| hook());
+    }
+
+    ///
+    /// Runs the rollback hooks, commit hooks are discarded.
+    pub(crate) fn rolled_back(&self) {
+        let rollback = std::mem::take(&mut *self.0.lock()).rollback;
+        rollback.into_iter().for_each(|hook| hook());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::txn::contention::Contender;
+
+use parking_lot::{Mutex, ReentrantMutex};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+thread_local! {
+    // real: TVar id, TVar lock
+    static LLS: RefCell<HashMap<u64, Held>> = RefCell::new(HashMap::new());
+}
+
+///
+/// Transaction which holds the lock of a transactional variable.
+pub(crate) type Owner = Arc<Mutex<Option<Arc<Contender>>>>;
+
+///
+/// Lock held by the ongoing transaction, along with the owner slot of its variable which is
+/// cleared when the lock is released.
+#[derive(Clone)]
+pub(crate) struct Held {
+    lock: Arc<ReentrantMutex<bool>>,
+    owner: Option<Owner>,
+}
+
+impl Held {
+    fn release(self) {
+        // Cleared before unlocking, the next holder sets it after acquiring the lock.
+        if let Some(owner) = self.owner {
+            *owner.lock() = None;
+        }
+
+        // SAFETY: Lock is acquired by this thread in `LockSet::acquire` and its guard is
+        // forgotten there.
+        unsafe { self.lock.force_unlock() }
+    }
+}
+
+///
+/// Locks of the transactional variables held by the ongoing transaction.
+///
+/// Locks stay acquired until the transaction either commits or rolls back.
+pub struct LockSet(HashMap<u64, Held>);
+
+impl LockSet {
+    fn new() -> Self {
+        Self(LLS.with(|ls| ls.borrow().clone()))
+    }
+
+    pub fn local() -> Self {
+        Self::new()
+    }
+
+    ///
+    /// Acquires the lock of the variable with the given id by waiting at most `timeout`.
+    /// Every lock is acquired only once in the course of a transaction.
+    pub fn acquire(
+        &mut self,
+        id: u64,
+        lock: &Arc<ReentrantMutex<bool>>,
+        timeout: Duration,
+    ) -> bool {
+        self.acquire_held(id, lock, None, timeout)
+    }
+
+    ///
+    /// Acquires the lock like [LockSet::acquire], and records the given contender as the owner
+    /// of the variable until the lock is released.
+    pub(crate) fn acquire_owned(
+        &mut self,
+        id: u64,
+        lock: &Arc<ReentrantMutex<bool>>,
+        owner: (&Owner, &Arc<Contender>),
+        timeout: Duration,
+    ) -> bool {
+        if self.acquire_held(id, lock, Some(owner.0), timeout) {
+            *owner.0.lock() = Some(owner.1.clone());
+            return true;
+        }
+
+        false
+    }
+
+    fn acquire_held(
+        &mut self,
+        id: u64,
+        lock: &Arc<ReentrantMutex<bool>>,
+        owner: Option<&Owner>,
+        timeout: Duration,
+    ) -> bool {
+        if self.holds(id) {
+            return true;
+        }
+
+        match lock.try_lock_for(timeout) {
+            Some(guard) => {
+                // Guard is released with `LockSet::release` when the transaction finishes.
+                std::mem::forget(guard);
+                let held = Held {
+                    lock: lock.clone(),
+                    owner: owner.cloned(),
+                };
+                self.0.insert(id, held);
+                self.flush();
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///
+    /// Is the lock of the variable with the given id held by this transaction.
+    pub fn holds(&self, id: u64) -> bool {
+        self.0.contains_key(&id)
+    }
+
+    ///
+    /// Releases the lock of the variable with the given id if it is held by this transaction.
+    pub fn unlock(&mut self, id: u64) {
+        if let Some(held) = self.0.remove(&id) {
+            held.release();
+            self.flush();
+        }
+    }
+
+    ///
+    /// Releases all the locks held by this transaction.
+    pub fn release(&mut self) {
+        self.0.drain().for_each(|(_, held)| held.release());
+        self.flush();
+    }
+
+    ///
+    /// Exchanges the locks of the ongoing transaction with the given ones.
+    pub(crate) fn swap_local(locks: &mut HashMap<u64, Held>) {
+        LLS.with(|ls| std::mem::swap(&mut *ls.borrow_mut(), locks))
+    }
+
+    fn flush(&self) {
+        LLS.with(|ls| {
+            let mut ls = ls.borrow_mut();
+            *ls = self.0.clone();
+        });
+    }
+}
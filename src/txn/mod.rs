@@ -1,19 +1,57 @@
+mod chain;
 mod conflicts;
 mod constants;
+#[cfg(test)]
+mod fixture;
+mod hooks;
+mod lockset;
 mod readset;
-mod utils;
+mod ssi;
 mod version;
 mod writeset;
 
+/// Version clock strategies
+pub mod clock;
+/// Contention management policies
+pub mod contention;
+/// Detachable transaction contexts
+pub mod context;
+/// Deadlock detection
+pub mod deadlock;
 /// Transactional system errors
 pub mod errors;
+/// Two-phase commit participants
+pub mod participant;
+/// In-flight transaction registry
+pub mod registry;
+/// Transactional hash map and set
+pub mod tmap;
+/// Transactional queue and bounded channel
+pub mod tqueue;
 /// Transaction management definitions
 pub mod transact;
+/// Transactional vector
+pub mod tvec;
 /// Transactional variable definitions
 pub mod vars;
+/// Redo log and crash recovery
+pub mod wal;
+/// Commit notifications of transactional variables
+pub mod watch;
 
 /// Prelude of transactional system
 pub mod prelude {
+    pub use super::clock::*;
+    pub use super::contention::*;
+    pub use super::context::*;
+    pub use super::deadlock::*;
+    pub use super::participant::*;
+    pub use super::registry::*;
+    pub use super::tmap::*;
+    pub use super::tqueue::*;
     pub use super::transact::*;
+    pub use super::tvec::*;
     pub use super::vars::*;
+    pub use super::wal::*;
+    pub use super::watch::*;
 }
@@ -0,0 +1,408 @@
+use crate::txn::errors::*;
+use crate::txn::wal::Wal;
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::{Arc, Weak};
+
+///
+/// Transactional resource which commits atomically along with a transaction.
+///
+/// Participants are enlisted into a transaction with [Txn::enlist](crate::txn::transact::Txn::enlist)
+/// and are driven through the two-phase commit with the id of the transaction execution.
+/// Every enlisted participant is either committed or rolled back once per execution.
+pub trait TxnParticipant: Send + Sync {
+    ///
+    /// Prepares the changes of the execution to commit. Once prepared, participant must be able
+    /// to commit them even if it fails meanwhile. Returning an error rolls back the execution and
+    /// the error is handled the same way as it is returned from the transaction closure.
+    fn prepare(&self, xid: u64) -> TxnResult<()>;
+
+    ///
+    /// Commits the prepared changes of the execution. If it fails, the execution stays in doubt
+    /// for this participant until it is recovered with
+    /// [TxnManager::recover](crate::txn::transact::TxnManager::recover). Manager also commits
+    /// it again along with the later two-phase commits, as long as the participant is alive
+    /// and reports the execution [in doubt](TxnParticipant::in_doubt).
+    fn commit(&self, xid: u64) -> TxnResult<()>;
+
+    ///
+    /// Rolls back the changes of the execution, whether they are prepared or not.
+    fn rollback(&self, xid: u64) -> TxnResult<()>;
+
+    ///
+    /// Executions that are prepared by this participant but neither committed nor rolled back.
+    ///
+    /// Commit decision of an execution is kept until every participant of it has committed, so
+    /// the executions which aren't reported here are taken as committed.
+    fn in_doubt(&self) -> Vec<u64> {
+        vec![]
+    }
+}
+
+///
+/// Participants enlisted into an execution of a transaction.
+#[derive(Clone, Default)]
+pub(crate) struct Participants(Arc<Mutex<Vec<Arc<dyn TxnParticipant>>>>);
+
+impl Participants {
+    pub(crate) fn enlist(&self, participant: Arc<dyn TxnParticipant>) {
+        let mut participants = self.0.lock();
+        if !participants.iter().any(|p| Arc::ptr_eq(p, &participant)) {
+            participants.push(participant);
+        }
+    }
+
+    ///
+    /// Number of the enlisted participants.
+    pub(crate) fn enlisted(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    ///
+    /// Asks every participant to prepare, stops at the first one that fails.
+    pub(crate) fn prepare(&self, xid: u64) -> TxnResult<()> {
+        let participants = self.0.lock().clone();
+        participants.iter().try_for_each(|p| p.prepare(xid))
+    }
+
+    ///
+    /// Commits every participant. Decision is kept before, so the participants that fail to
+    /// commit can be recovered later. Decisions that are still pending for the other executions
+    /// are collected afterwards.
+    pub(crate) fn commit(&self, xid: u64, decisions: &Decisions) {
+        let participants = std::mem::take(&mut *self.0.lock());
+        if participants.is_empty() {
+            return;
+        }
+
+        decisions.decide(xid, &participants);
+        participants
+            .iter()
+            .filter(|p| p.commit(xid).is_ok())
+            .for_each(|p| decisions.acknowledge(xid, &**p));
+        decisions.settle(xid);
+
+        decisions.collect();
+    }
+
+    ///
+    /// Rolls back every participant. Rollback is presumed for the executions without a commit
+    /// decision, so the participants that fail to roll back are recovered the same way.
+    pub(crate) fn rollback(&self, xid: u64) {
+        let participants = std::mem::take(&mut *self.0.lock());
+        participants.iter().for_each(|p| {
+            let _ = p.rollback(xid);
+        });
+    }
+}
+
+///
+/// Commit decision of an execution which is not acknowledged by all of its participants yet.
+struct Decision {
+    /// Number of the participants which haven't acknowledged
+    pending: usize,
+    /// Participants which haven't acknowledged, `None` if the decision is recovered from the log
+    participants: Option<Vec<Weak<dyn TxnParticipant>>>,
+    /// Participants are being committed by the execution itself
+    committing: bool,
+}
+
+///
+/// Commit decisions of a transaction manager which are not acknowledged by all of their
+/// participants yet.
+///
+/// Decisions of a [durable](crate::txn::transact::TxnManager::durable) manager are logged to
+/// its redo log, and the ones that are pending survive a restart.
+#[derive(Default)]
+pub(crate) struct Decisions {
+    decisions: Mutex<HashMap<u64, Decision>>,
+    /// Held while the pending decisions are resolved, so a participant isn't committed twice
+    resolving: Mutex<()>,
+    /// Redo log the acknowledgements are logged to, if the manager is durable
+    wal: Option<Arc<Wal>>,
+}
+
+impl Decisions {
+    ///
+    /// Decisions logged to the given redo log, starting with the given pending ones which are
+    /// recovered from it.
+    pub(crate) fn logged(wal: Arc<Wal>, pending: HashMap<u64, usize>) -> Self {
+        let decisions = pending
+            .into_iter()
+            .map(|(xid, pending)| {
+                let decision = Decision {
+                    pending,
+                    participants: None,
+                    committing: false,
+                };
+                (xid, decision)
+            })
+            .collect();
+
+        Self {
+            decisions: Mutex::new(decisions),
+            resolving: Mutex::new(()),
+            wal: Some(wal),
+        }
+    }
+
+    ///
+    /// Keeps the commit decision of the given execution until the given participants
+    /// acknowledge it. Decisions of a durable manager are logged along with the commit.
+    fn decide(&self, xid: u64, participants: &[Arc<dyn TxnParticipant>]) {
+        let decision = Decision {
+            pending: participants.len(),
+            participants: Some(participants.iter().map(Arc::downgrade).collect()),
+            committing: true,
+        };
+        self.decisions.lock().insert(xid, decision);
+    }
+
+    ///
+    /// Leaves the participants which have failed to commit the given execution to be resolved
+    /// later.
+    fn settle(&self, xid: u64) {
+        if let Some(decision) = self.decisions.lock().get_mut(&xid) {
+            decision.committing = false;
+        }
+    }
+
+    ///
+    /// Forgets the given participant for the decision of the given execution, and the decision
+    /// once every participant has acknowledged it.
+    fn acknowledge(&self, xid: u64, participant: &dyn TxnParticipant) {
+        {
+            let mut decisions = self.decisions.lock();
+            let decision = match decisions.get_mut(&xid) {
+                Some(decision) => decision,
+                None => return,
+            };
+
+            // Participants of a recovered decision aren't known, the ones that recover it are
+            // taken as them.
+            if let Some(participants) = &mut decision.participants {
+                let participant: *const dyn TxnParticipant = participant;
+                match participants
+                    .iter()
+                    .position(|p| ptr::addr_eq(p.as_ptr(), participant))
+                {
+                    Some(i) => {
+                        participants.swap_remove(i);
+                    }
+                    None => return,
+                }
+            }
+
+            decision.pending = decision.pending.saturating_sub(1);
+            if decision.pending == 0 {
+                decisions.remove(&xid);
+            }
+        }
+
+        if let Some(wal) = &self.wal {
+            // Lost acknowledgement only keeps the decision until the participant recovers.
+            let _ = wal.acknowledge(xid);
+        }
+    }
+
+    ///
+    /// Asks the participants which are alive and haven't acknowledged a decision yet whether
+    /// the execution is still in doubt for them. They are committed if it is, and the
+    /// decision is acknowledged on their behalf once it isn't.
+    fn collect(&self) {
+        // Some other execution is resolving them already.
+        let _resolving = match self.resolving.try_lock() {
+            Some(resolving) => resolving,
+            None => return,
+        };
+
+        let pending: Vec<(u64, Arc<dyn TxnParticipant>)> = self
+            .decisions
+            .lock()
+            .iter()
+            .filter(|(_, decision)| !decision.committing)
+            .flat_map(|(xid, decision)| {
+                let participants = decision.participants.iter().flatten();
+                participants
+                    .filter_map(Weak::upgrade)
+                    .map(move |p| (*xid, p))
+            })
+            .collect();
+
+        for (xid, participant) in pending {
+            if !participant.in_doubt().contains(&xid) || participant.commit(xid).is_ok() {
+                self.acknowledge(xid, &*participant);
+            }
+        }
+    }
+
+    ///
+    /// Resolves the executions that are in doubt for the given participant. Executions that
+    /// are decided to commit are committed, the others are rolled back.
+    pub(crate) fn recover(&self, participant: &dyn TxnParticipant) -> TxnResult<()> {
+        let _resolving = self.resolving.lock();
+
+        for xid in participant.in_doubt() {
+            let committing = self.decisions.lock().get(&xid).map(|d| d.committing);
+
+            match committing {
+                // Execution is committing the participant itself.
+                Some(true) => {}
+                Some(false) => {
+                    participant.commit(xid)?;
+                    self.acknowledge(xid, participant);
+                }
+                None => participant.rollback(xid)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod participant_tests {
+    use super::*;
+    use crate::txn::fixture::{txn, txn_in};
+    use crate::txn::prelude::*;
+    use std::collections::BTreeSet;
+
+    #[derive(Default)]
+    struct Journal {
+        log: Mutex<Vec<String>>,
+        prepared: Mutex<BTreeSet<u64>>,
+        veto: bool,
+        unavailable: Mutex<bool>,
+    }
+
+    impl TxnParticipant for Journal {
+        fn prepare(&self, xid: u64) -> TxnResult<()> {
+            let state = Txn::get_local().state();
+            assert!(matches!(*state, TransactionState::Preparing));
+
+            self.log.lock().push("prepare".into());
+            if self.veto {
+                return Err(TxnError::Abort);
+            }
+
+            self.prepared.lock().insert(xid);
+            Ok(())
+        }
+
+        fn commit(&self, xid: u64) -> TxnResult<()> {
+            if *self.unavailable.lock() {
+                return Err(TxnError::AbortWithContext("unavailable".into()));
+            }
+
+            self.log.lock().push("commit".into());
+            self.prepared.lock().remove(&xid);
+            Ok(())
+        }
+
+        fn rollback(&self, xid: u64) -> TxnResult<()> {
+            self.log.lock().push("rollback".into());
+            self.prepared.lock().remove(&xid);
+            Ok(())
+        }
+
+        fn in_doubt(&self) -> Vec<u64> {
+            self.prepared.lock().iter().copied().collect()
+        }
+    }
+
+    #[test]
+    fn participant_two_phase_commit() {
+        let journal = Arc::new(Journal::default());
+        let mut tvar = TVar::new(100_u64);
+
+        txn()
+            .begin(|t| {
+                t.enlist(journal.clone());
+                t.enlist(journal.clone());
+                t.write(&mut tvar, 200);
+            })
+            .unwrap();
+
+        assert_eq!(*journal.log.lock(), vec!["prepare", "commit"]);
+        assert!(journal.in_doubt().is_empty());
+        assert_eq!(tvar.get_data(), 200);
+    }
+
+    #[test]
+    fn participant_veto() {
+        let journal = Arc::new(Journal::default());
+        let veto = Arc::new(Journal {
+            veto: true,
+            ..Journal::default()
+        });
+        let mut tvar = TVar::new(100_u64);
+
+        let res = txn().begin(|t| {
+            t.enlist(journal.clone());
+            t.enlist(veto.clone());
+            t.write(&mut tvar, 200);
+        });
+
+        assert!(matches!(res, Err(TxnError::Abort)));
+        assert_eq!(*journal.log.lock(), vec!["prepare", "rollback"]);
+        assert_eq!(*veto.log.lock(), vec!["prepare", "rollback"]);
+        assert_eq!(tvar.get_data(), 100);
+    }
+
+    #[test]
+    fn participant_decisions_collected() {
+        let manager = TxnManager::isolated();
+        let txn = txn_in(&manager);
+
+        let journal = Arc::new(Journal::default());
+        *journal.unavailable.lock() = true;
+
+        let xid = txn
+            .begin(|t| {
+                t.enlist(journal.clone());
+                t.xid()
+            })
+            .unwrap();
+        assert_eq!(journal.in_doubt(), vec![xid]);
+
+        // Next two-phase commit commits the pending decision again.
+        *journal.unavailable.lock() = false;
+        let other = Arc::new(Journal::default());
+        txn.begin(|t| t.enlist(other.clone())).unwrap();
+
+        assert!(journal.in_doubt().is_empty());
+        assert_eq!(*journal.log.lock(), vec!["prepare", "commit"]);
+
+        // Decision is forgotten once it is acknowledged.
+        journal.prepared.lock().insert(xid);
+        manager.recover(&*journal).unwrap();
+        assert_eq!(*journal.log.lock(), vec!["prepare", "commit", "rollback"]);
+    }
+
+    #[test]
+    fn participant_recovery() {
+        let journal = Arc::new(Journal::default());
+        *journal.unavailable.lock() = true;
+
+        let xid = txn()
+            .begin(|t| {
+                t.enlist(journal.clone());
+                t.xid()
+            })
+            .unwrap();
+
+        // Commit is decided, participant hasn't learned it.
+        assert_eq!(journal.in_doubt(), vec![xid]);
+
+        // Prepared by an execution which never decided.
+        journal.prepared.lock().insert(u64::MAX);
+
+        *journal.unavailable.lock() = false;
+        TxnManager::global().recover(&*journal).unwrap();
+
+        assert!(journal.in_doubt().is_empty());
+        assert_eq!(*journal.log.lock(), vec!["prepare", "commit", "rollback"]);
+    }
+}
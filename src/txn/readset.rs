@@ -1,22 +1,17 @@
-use super::utils;
-use super::vars::TVar;
 use crate::txn::conflicts::*;
-use crate::txn::version::{Var, Version};
+use crate::txn::transact::Commits;
+use crate::txn::version::Entry;
 
 use std::cell::RefCell;
-use std::{
-    borrow::{Borrow, BorrowMut},
-    collections::HashSet,
-};
+use std::collections::HashMap;
+use std::time::Instant;
 
 thread_local! {
-    // real: Arc<TVar<T>>
-    // virtual: Var
-    pub(crate) static LRS: RefCell<HashSet<Version>> = RefCell::new(HashSet::new());
+    // real: TVar id, Entry of TVar<T> holding the read version
+    pub(crate) static LRS: RefCell<HashMap<u64, Entry>> = RefCell::new(HashMap::new());
 }
 
-// HashSet<*mut LockVar<T>>
-pub struct ReadSet(pub HashSet<Version>);
+pub struct ReadSet(pub(crate) HashMap<u64, Entry>);
 
 impl ReadSet {
     fn new() -> Self {
@@ -30,33 +25,29 @@ impl ReadSet {
         Self::new()
     }
 
-    pub fn get_all(&self) -> Vec<Var> {
-        self.0.iter().map(|e| e.read()).collect()
+    ///
+    /// Entry of the variable that is read previously with the given id in this transaction.
+    pub(crate) fn get_by_id(&self, id: u64) -> Option<&Entry> {
+        self.0.get(&id)
     }
 
-    pub fn get_all_versions(&self) -> Vec<&Version> {
-        self.0.iter().collect()
-    }
-
-    pub fn get<T: Clone>(&self, seek: Version) -> Option<Var> {
-        self.0.iter().find(|x| **x == seek).map(|f| f.read())
-    }
-
-    pub fn add(mut self, e: Var) {
-        let v = Version::Read(e);
-        self.0.insert(v);
+    ///
+    /// Adds the entry to the read set of the ongoing transaction, returns the size of the set.
+    pub(crate) fn add(mut self, e: Entry) -> usize {
+        self.0.insert(e.id, e);
 
         LRS.with(|hs| {
             let mut hs = hs.borrow_mut();
             *hs = self.0.clone();
         });
+
+        self.0.len()
     }
 
-    pub(in crate::txn) fn cmps<T: 'static + Clone + Sync + Send>(&self) -> Vec<Compare> {
+    pub(in crate::txn) fn cmps(&self) -> Vec<Compare> {
         let mut cmset = Vec::with_capacity(self.0.len());
-        self.0.iter().for_each(|v| {
-            let var: TVar<T> = utils::version_to_tvar(v);
-            let cmp = Compare::new(var.modrev, var.modrev == var.stamp, CompareSet::ReadLocal);
+        self.0.values().for_each(|e| {
+            let cmp = Compare::new(e.stamp, e.is_current(), CompareSet::ReadLocal);
 
             cmset.push(cmp);
         });
@@ -64,18 +55,28 @@ impl ReadSet {
         cmset
     }
 
-    /// First stamp
-    pub(crate) fn first<T: 'static + Clone + Sync + Send>(&self) -> u64 {
-        let mut min_stamp = u64::MAX;
-        for x in self.0.iter() {
-            let v: TVar<T> = utils::version_to_tvar(x);
-            // dbg!(v.stamp);
-            if v.stamp < min_stamp {
-                min_stamp = v.stamp;
-            }
-        }
-        // dbg!("=======");
-        min_stamp
+    ///
+    /// Checks that every read variable is still at the version that is read and it is not
+    /// locked by some other transaction.
+    pub(in crate::txn) fn validate(&self) -> bool {
+        self.0.values().all(Entry::validate)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///
+    /// Blocks until some other transaction commits a new version of any variable that is read,
+    /// or until the given deadline passes.
+    pub(in crate::txn) fn wait_for_change(&self, commits: &Commits, deadline: Option<Instant>) {
+        commits.wait_while(|| self.0.values().all(Entry::is_current), deadline);
+    }
+
+    ///
+    /// Exchanges the read set of the ongoing transaction with the given one.
+    pub(crate) fn swap_local(reads: &mut HashMap<u64, Entry>) {
+        LRS.with(|hs| std::mem::swap(&mut *hs.borrow_mut(), reads))
     }
 
     pub fn clear(&mut self) {
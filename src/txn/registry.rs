@@ -0,0 +1,240 @@
+use crate::txn::transact::{TransactionConcurrency, TransactionIsolation, TransactionState, Txn};
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+///
+/// Snapshot of an in-flight transaction, taken with
+/// [TxnManager::active_transactions](crate::txn::transact::TxnManager::active_transactions).
+#[derive(Debug, Clone)]
+pub struct TxnInfo {
+    /// Id of the transaction config
+    pub config_id: u64,
+    /// Label of the transaction
+    pub label: String,
+    /// Id of the thread which runs the ongoing execution
+    pub tid: ThreadId,
+    /// Txn isolation level
+    pub iso: TransactionIsolation,
+    /// Txn concurrency level
+    pub cc: TransactionConcurrency,
+    /// State of the ongoing execution
+    pub state: TransactionState,
+    /// Instant the first execution has started at
+    pub started: Instant,
+    /// Time passed since the first execution has started
+    pub elapsed: Duration,
+    /// Number of executions before the ongoing one
+    pub retries: u64,
+    /// Read timestamp of the ongoing execution
+    pub rts: u64,
+    /// Number of variables in the read set of the ongoing execution
+    pub reads: usize,
+    /// Number of variables in the write set of the ongoing execution
+    pub writes: usize,
+}
+
+///
+/// Read timestamp and the sizes of the read and write sets of an execution. Sets are local to
+/// the thread running the execution, so their sizes are mirrored here for the other threads.
+#[derive(Default)]
+pub(crate) struct Footprint {
+    rts: AtomicU64,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+}
+
+impl Footprint {
+    pub(crate) fn set_rts(&self, rts: u64) {
+        self.rts.store(rts, Ordering::SeqCst);
+    }
+
+    pub(crate) fn set_reads(&self, reads: usize) {
+        self.reads.store(reads, Ordering::SeqCst);
+    }
+
+    pub(crate) fn set_writes(&self, writes: usize) {
+        self.writes.store(writes, Ordering::SeqCst);
+    }
+
+    pub(crate) fn rts(&self) -> u64 {
+        self.rts.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn writes(&self) -> usize {
+        self.writes.load(Ordering::SeqCst)
+    }
+}
+
+///
+/// Transactions of a manager which have started and haven't finished yet, keyed by the birth
+/// timestamps of them. Ongoing execution of every transaction is kept.
+///
+/// Transactions are tracked only while tracking is enabled, otherwise starting and finishing
+/// a transaction doesn't touch the registry beyond an atomic load.
+#[derive(Default)]
+pub(crate) struct Registry {
+    enabled: AtomicBool,
+    /// Number of the tracked transactions
+    tracked: AtomicUsize,
+    txns: Mutex<HashMap<u64, Txn>>,
+}
+
+impl Registry {
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.txns.lock().clear();
+            self.tracked.store(0, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Registers the given execution as the ongoing one of its transaction, if tracking is
+    /// enabled.
+    pub(crate) fn track(&self, execution: Txn) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut txns = self.txns.lock();
+        if txns
+            .insert(execution.contender.birth(), execution)
+            .is_none()
+        {
+            self.tracked.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn untrack(&self, birth: u64) {
+        if self.tracked.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        if self.txns.lock().remove(&birth).is_some() {
+            self.tracked.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    ///
+    /// Keeps the transaction with the given birth timestamp registered until the returned
+    /// guard is dropped.
+    pub(crate) fn scope(self: &Arc<Self>, birth: u64) -> Tracked {
+        Tracked {
+            registry: self.clone(),
+            birth,
+        }
+    }
+
+    ///
+    /// Ongoing transactions, the oldest first.
+    pub(crate) fn active(&self) -> Vec<TxnInfo> {
+        let mut active: Vec<TxnInfo> = self.txns.lock().values().map(Txn::info).collect();
+        active.sort_by_key(|info| info.started);
+        active
+    }
+
+    ///
+    /// Smallest read timestamp of the ongoing executions.
+    pub(crate) fn oldest_rts(&self) -> Option<u64> {
+        self.txns
+            .lock()
+            .values()
+            .map(|txn| txn.footprint.rts())
+            .min()
+    }
+}
+
+///
+/// Unregisters a transaction when it is dropped.
+pub(crate) struct Tracked {
+    registry: Arc<Registry>,
+    birth: u64,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.registry.untrack(self.birth);
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use crate::txn::fixture::txn_of;
+    use crate::txn::prelude::*;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn registry_active_transactions() {
+        let manager = TxnManager::isolated();
+        manager.set_tracking(true);
+
+        let txn = txn_of(&manager, TransactionIsolation::RepeatableRead, "registry");
+
+        let read = manager.tvar(100_u64);
+        let written = manager.tvar(100_u64);
+        let barrier = Arc::new(Barrier::new(2));
+
+        let t = {
+            let (txn, read, mut written) = (txn.clone(), read.clone(), written.clone());
+            let barrier = barrier.clone();
+
+            thread::spawn(move || {
+                txn.begin(|t| {
+                    let v = t.read(&read);
+                    t.write(&mut written, v + 1);
+
+                    barrier.wait();
+                    barrier.wait();
+                })
+                .unwrap();
+            })
+        };
+
+        barrier.wait();
+        let active = manager.active_transactions();
+        assert_eq!(active.len(), 1);
+
+        let info = &active[0];
+        assert_eq!(info.label, "registry");
+        assert_eq!(info.tid, t.thread().id());
+        assert!(matches!(info.state, TransactionState::Active));
+        assert!(matches!(info.cc, TransactionConcurrency::Optimistic));
+        assert_eq!((info.retries, info.reads, info.writes), (0, 1, 1));
+        assert_eq!(manager.oldest_active_rts(), Some(info.rts));
+
+        barrier.wait();
+        t.join().unwrap();
+
+        assert!(manager.active_transactions().is_empty());
+        assert_eq!(manager.oldest_active_rts(), None);
+
+        // Detached transactions are in flight until they finish.
+        let ctx = txn.start().unwrap();
+        let active = manager.active_transactions();
+        assert_eq!(active.len(), 1);
+        assert!(matches!(active[0].state, TransactionState::Suspended));
+
+        ctx.commit().unwrap();
+        assert!(manager.active_transactions().is_empty());
+
+        // Transactions aren't tracked unless it is enabled.
+        manager.set_tracking(false);
+        let ctx = txn.start().unwrap();
+        assert!(manager.active_transactions().is_empty());
+        ctx.commit().unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use crate::txn::clock::Clock;
+use crate::txn::version::Committed;
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+const ACTIVE: u64 = 0;
+const COMMITTING: u64 = u64::MAX - 1;
+const ABORTED: u64 = u64::MAX;
+
+///
+/// Serialization state of a transaction execution for Serializable Snapshot Isolation.
+///
+/// An rw-antidependency `T1 -rw-> T2` exists when `T1` reads a version of a variable and a
+/// concurrent `T2` writes the next version of it. A transaction that has both an incoming and an
+/// outgoing rw-antidependency might be the pivot of a dependency cycle, it is aborted.
+#[derive(Default)]
+pub(crate) struct SsiState {
+    /// Read timestamp of the transaction
+    start: AtomicU64,
+    /// Commit timestamp of the transaction, or its status if it is not committed.
+    finish: AtomicU64,
+    /// Some concurrent transaction has read a version this transaction overwrites.
+    inbound: AtomicBool,
+    /// This transaction has read a version some concurrent transaction overwrites.
+    outbound: AtomicBool,
+}
+
+impl SsiState {
+    pub(crate) fn start(&self, rts: u64) {
+        self.start.store(rts, Ordering::SeqCst);
+    }
+
+    pub(crate) fn committed(&self, wts: u64) {
+        self.finish.store(wts, Ordering::SeqCst);
+    }
+
+    pub(crate) fn aborted(&self) {
+        self.finish.store(ABORTED, Ordering::SeqCst);
+    }
+
+    fn is_committed(&self) -> bool {
+        !matches!(self.finish.load(Ordering::SeqCst), ACTIVE | ABORTED)
+    }
+
+    ///
+    /// Is this transaction concurrent with the transactions that read at the given timestamp or
+    /// after. Transactions which read at its commit timestamp see everything it has written.
+    fn overlaps(&self, ts: u64) -> bool {
+        match self.finish.load(Ordering::SeqCst) {
+            ABORTED => false,
+            ACTIVE | COMMITTING => true,
+            finish => finish > ts,
+        }
+    }
+
+    fn is_pivot(&self) -> bool {
+        self.inbound.load(Ordering::SeqCst) && self.outbound.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Records `self -rw-> writer` for every writer of the versions that are committed after
+    /// the version this transaction has read. Returns false if the transaction needs to abort.
+    ///
+    /// Changes on the rw-antidependencies are serialized with the given lock of the manager.
+    pub(crate) fn read_overwritten<T>(&self, ssi: &Mutex<()>, versions: &[Committed<T>]) -> bool {
+        let _ssi = ssi.lock();
+
+        let writers = versions.iter().filter_map(|v| v.writer.as_ref());
+        for writer in writers {
+            if writer.is_committed() && writer.outbound.load(Ordering::SeqCst) {
+                // Writer is a committed pivot, it can't be aborted anymore.
+                return false;
+            }
+
+            writer.inbound.store(true, Ordering::SeqCst);
+            self.outbound.store(true, Ordering::SeqCst);
+        }
+
+        !self.is_pivot()
+    }
+
+    ///
+    /// Records `reader -rw-> self` for every concurrent reader of the variables that this
+    /// transaction writes, and decides whether this transaction can commit.
+    ///
+    /// Transaction is considered committed until [SsiState::committed] or [SsiState::aborted]
+    /// is called, if it can commit.
+    pub(crate) fn try_commit(self: &Arc<Self>, ssi: &Mutex<()>, readers: Vec<&SiReads>) -> bool {
+        let _ssi = ssi.lock();
+        let start = self.start.load(Ordering::SeqCst);
+
+        let readers: Vec<Arc<SsiState>> = readers
+            .into_iter()
+            .flat_map(SiReads::readers)
+            .filter(|r| !Arc::ptr_eq(r, self) && r.overlaps(start))
+            .collect();
+
+        // Reader is a committed pivot, it can't be aborted anymore.
+        if readers
+            .iter()
+            .any(|r| r.is_committed() && r.inbound.load(Ordering::SeqCst))
+        {
+            return false;
+        }
+
+        if !readers.is_empty() {
+            self.inbound.store(true, Ordering::SeqCst);
+        }
+
+        if self.is_pivot() {
+            return false;
+        }
+
+        readers
+            .iter()
+            .for_each(|r| r.outbound.store(true, Ordering::SeqCst));
+        self.finish.store(COMMITTING, Ordering::SeqCst);
+
+        true
+    }
+}
+
+///
+/// Transactions that have read a transactional variable, along with the clock the variable is
+/// bound to.
+pub(crate) struct SiReads(Mutex<Vec<Arc<SsiState>>>, Arc<Clock>);
+
+impl SiReads {
+    pub(crate) fn new(clock: Arc<Clock>) -> Self {
+        Self(Mutex::new(Vec::new()), clock)
+    }
+
+    pub(crate) fn register(&self, txn: &Arc<SsiState>) {
+        let mut readers = self.0.lock();
+
+        if !readers.iter().any(|r| Arc::ptr_eq(r, txn)) {
+            self.collect(&mut readers);
+            readers.push(txn.clone());
+        }
+    }
+
+    fn readers(&self) -> Vec<Arc<SsiState>> {
+        let mut readers = self.0.lock();
+        self.collect(&mut readers);
+        readers.clone()
+    }
+
+    ///
+    /// Forgets the readers which can't be concurrent with any ongoing transaction anymore.
+    fn collect(&self, readers: &mut Vec<Arc<SsiState>>) {
+        if !readers.is_empty() {
+            let horizon = self.1.horizon();
+            readers.retain(|r| r.overlaps(horizon));
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::txn::clock::Clock;
+use crate::txn::constants::{DEFAULT_TMAP_BUCKETS, DEFAULT_TX_TIMEOUT};
+use crate::txn::transact::{Txn, TxnManager};
+use crate::txn::vars::TVar;
+
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+type Bucket<K, V> = Arc<HashMap<K, TVar<V>>>;
+
+///
+/// Transactional hash map.
+///
+/// Keys are spread over a fixed number of buckets, and every bucket maps its keys to the
+/// transactional variables of their values. Updating the value of a key only writes its own
+/// variable, so it conflicts with the transactions which access the same key. Inserting and
+/// removing keys writes the bucket, so it conflicts with the transactions which access the
+/// keys of the same bucket.
+///
+/// Operations run in the given transaction and compose with any other transactional operation
+/// in it. Clones of a map are handles to the same map.
+#[derive(Clone)]
+pub struct TMap<K, V>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+    V: Clone + Any + Send + Sync,
+{
+    buckets: Arc<Vec<TVar<Bucket<K, V>>>>,
+    hasher: RandomState,
+    /// Clock of the manager the values are bound to
+    clock: Arc<Clock>,
+}
+
+impl<K, V> TMap<K, V>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+    V: Clone + Any + Send + Sync,
+{
+    ///
+    /// Empty map.
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_TMAP_BUCKETS)
+    }
+
+    ///
+    /// Empty map with the given number of buckets. More buckets make the inserts and the
+    /// removals of different keys less likely to conflict.
+    pub fn with_buckets(buckets: usize) -> Self {
+        Self::with_clock(buckets, TxnManager::global().clock.clone())
+    }
+
+    ///
+    /// Empty map bound to the given transaction manager.
+    pub fn with_manager(manager: &TxnManager) -> Self {
+        Self::with_clock(DEFAULT_TMAP_BUCKETS, manager.clock.clone())
+    }
+
+    fn with_clock(buckets: usize, clock: Arc<Clock>) -> Self {
+        let buckets = (0..buckets.max(1))
+            .map(|_| HashMap::new())
+            .map(|bucket| TVar::with_clock(Arc::new(bucket), DEFAULT_TX_TIMEOUT, clock.clone()))
+            .collect();
+
+        Self {
+            buckets: Arc::new(buckets),
+            hasher: RandomState::new(),
+            clock,
+        }
+    }
+
+    fn bucket(&self, k: &K) -> &TVar<Bucket<K, V>> {
+        &self.buckets[self.hasher.hash_one(k) as usize % self.buckets.len()]
+    }
+
+    pub fn get(&self, t: &mut Txn, k: &K) -> Option<V> {
+        let bucket = t.read(self.bucket(k));
+        bucket.get(k).map(|value| t.read(value))
+    }
+
+    pub fn contains_key(&self, t: &mut Txn, k: &K) -> bool {
+        t.read(self.bucket(k)).contains_key(k)
+    }
+
+    ///
+    /// Inserts the given value for the given key, and returns the previous value of it.
+    pub fn insert(&self, t: &mut Txn, k: K, v: V) -> Option<V> {
+        let var = self.bucket(&k);
+        let bucket = t.read(var);
+
+        if let Some(value) = bucket.get(&k) {
+            let previous = t.read(value);
+            t.write(&mut value.clone(), v);
+            return Some(previous);
+        }
+
+        let mut bucket = (*bucket).clone();
+        bucket.insert(
+            k,
+            TVar::with_clock(v, DEFAULT_TX_TIMEOUT, self.clock.clone()),
+        );
+        t.write(&mut var.clone(), Arc::new(bucket));
+
+        None
+    }
+
+    ///
+    /// Removes the given key, and returns its value.
+    pub fn remove(&self, t: &mut Txn, k: &K) -> Option<V> {
+        let var = self.bucket(k);
+        let bucket = t.read(var);
+        let previous = t.read(bucket.get(k)?);
+
+        let mut bucket = (*bucket).clone();
+        bucket.remove(k);
+        t.write(&mut var.clone(), Arc::new(bucket));
+
+        Some(previous)
+    }
+
+    ///
+    /// Number of keys. It reads every bucket, so it conflicts with every insert and removal.
+    pub fn len(&self, t: &mut Txn) -> usize {
+        self.buckets.iter().map(|var| t.read(var).len()).sum()
+    }
+
+    pub fn is_empty(&self, t: &mut Txn) -> bool {
+        self.len(t) == 0
+    }
+
+    ///
+    /// Keys in no particular order.
+    pub fn keys(&self, t: &mut Txn) -> Vec<K> {
+        self.buckets
+            .iter()
+            .flat_map(|var| t.read(var).keys().cloned().collect::<Vec<K>>())
+            .collect()
+    }
+
+    ///
+    /// Keys and values in no particular order.
+    pub fn entries(&self, t: &mut Txn) -> Vec<(K, V)> {
+        let mut entries = Vec::new();
+        for var in self.buckets.iter() {
+            for (k, value) in t.read(var).iter() {
+                entries.push((k.clone(), t.read(value)));
+            }
+        }
+
+        entries
+    }
+}
+
+impl<K, V> Default for TMap<K, V>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+    V: Clone + Any + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Transactional hash set.
+///
+/// [TMap] without values. Inserting and removing elements conflict with the transactions which
+/// access the elements of the same bucket.
+#[derive(Clone)]
+pub struct TSet<K>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+{
+    map: TMap<K, ()>,
+}
+
+impl<K> TSet<K>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+{
+    ///
+    /// Empty set.
+    pub fn new() -> Self {
+        Self { map: TMap::new() }
+    }
+
+    ///
+    /// Empty set with the given number of buckets.
+    pub fn with_buckets(buckets: usize) -> Self {
+        Self {
+            map: TMap::with_buckets(buckets),
+        }
+    }
+
+    ///
+    /// Empty set bound to the given transaction manager.
+    pub fn with_manager(manager: &TxnManager) -> Self {
+        Self {
+            map: TMap::with_manager(manager),
+        }
+    }
+
+    pub fn contains(&self, t: &mut Txn, k: &K) -> bool {
+        self.map.contains_key(t, k)
+    }
+
+    ///
+    /// Inserts the given element. Returns false if it is in the set already.
+    pub fn insert(&self, t: &mut Txn, k: K) -> bool {
+        if self.map.contains_key(t, &k) {
+            return false;
+        }
+
+        self.map.insert(t, k, ());
+        true
+    }
+
+    ///
+    /// Removes the given element. Returns false if it isn't in the set.
+    pub fn remove(&self, t: &mut Txn, k: &K) -> bool {
+        self.map.remove(t, k).is_some()
+    }
+
+    pub fn len(&self, t: &mut Txn) -> usize {
+        self.map.len(t)
+    }
+
+    pub fn is_empty(&self, t: &mut Txn) -> bool {
+        self.map.is_empty(t)
+    }
+
+    ///
+    /// Elements in no particular order.
+    pub fn to_vec(&self, t: &mut Txn) -> Vec<K> {
+        self.map.keys(t)
+    }
+}
+
+impl<K> Default for TSet<K>
+where
+    K: Hash + Eq + Clone + Any + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tmap_tests {
+    use crate::txn::fixture::txn;
+    use crate::txn::prelude::*;
+    use std::thread;
+
+    #[test]
+    fn tmap_operations() {
+        let map = TMap::new();
+
+        let inserted = txn()
+            .begin(|t| {
+                (
+                    map.insert(t, 1, "one"),
+                    map.insert(t, 2, "two"),
+                    map.insert(t, 1, "uno"),
+                )
+            })
+            .unwrap();
+        assert_eq!(inserted, (None, None, Some("one")));
+
+        let read = txn()
+            .begin(|t| (map.get(t, &1), map.contains_key(t, &3), map.len(t)))
+            .unwrap();
+        assert_eq!(read, (Some("uno"), false, 2));
+
+        assert_eq!(txn().begin(|t| map.remove(t, &1)).unwrap(), Some("uno"));
+        assert_eq!(txn().begin(|t| map.remove(t, &1)).unwrap(), None);
+        assert_eq!(txn().begin(|t| map.entries(t)).unwrap(), vec![(2, "two")]);
+
+        // Moving between the collections is atomic.
+        let set = TSet::new();
+        txn()
+            .begin(|t| {
+                let v = map.remove(t, &2).unwrap();
+                assert!(set.insert(t, v));
+                assert!(!set.insert(t, v));
+            })
+            .unwrap();
+        let moved = txn()
+            .begin(|t| (map.is_empty(t), set.to_vec(t), set.contains(t, &"two")))
+            .unwrap();
+        assert_eq!(moved, (true, vec!["two"], true));
+
+        assert!(txn().begin(|t| set.remove(t, &"two")).unwrap());
+        assert!(txn().begin(|t| set.is_empty(t)).unwrap());
+    }
+
+    #[test]
+    fn tmap_keys_do_not_conflict() {
+        // Single bucket, so only the values tell the keys apart.
+        let map = TMap::with_buckets(1);
+        txn()
+            .begin(|t| {
+                map.insert(t, 1_u64, 0_u64);
+                map.insert(t, 2_u64, 0_u64);
+            })
+            .unwrap();
+
+        let mut ctx = txn().start().unwrap();
+        ctx.resume(|t| {
+            let v = map.get(t, &1).unwrap();
+            map.insert(t, 1, v + 1);
+        })
+        .unwrap();
+
+        txn().begin(|t| map.insert(t, 2, 1)).unwrap();
+        ctx.commit().unwrap();
+
+        let values = txn().begin(|t| (map.get(t, &1), map.get(t, &2))).unwrap();
+        assert_eq!(values, (Some(1), Some(1)));
+    }
+
+    #[test]
+    fn tmap_concurrent_increments() {
+        let map = TMap::new();
+        let txn = txn();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let (map, txn) = (map.clone(), txn.clone());
+                thread::spawn(move || {
+                    for k in 0..20_u64 {
+                        txn.begin(|t| {
+                            let v = map.get(t, &(k % 5)).unwrap_or(0);
+                            map.insert(t, k % 5, v + 1);
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads.into_iter() {
+            t.join().unwrap();
+        }
+
+        let mut entries = txn.begin(|t| map.entries(t)).unwrap();
+        entries.sort();
+        assert_eq!(entries, (0..5).map(|k| (k, 16)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::txn::clock::Clock;
+use crate::txn::constants::DEFAULT_TX_TIMEOUT;
+use crate::txn::errors::*;
+use crate::txn::transact::{Txn, TxnManager};
+use crate::txn::vars::TVar;
+
+use std::any::Any;
+use std::sync::Arc;
+
+type Link<T> = Option<Arc<Node<T>>>;
+
+struct Node<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    value: T,
+    next: TVar<Link<T>>,
+}
+
+impl<T> Drop for Node<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    fn drop(&mut self) {
+        // Dropping a node drops the rest of the queue linked to it, so the following nodes are
+        // unlinked in a loop instead of dropping them recursively.
+        let mut next = Self::unlink(&mut self.next);
+        while let Some(node) = next.pop() {
+            if let Ok(mut node) = Arc::try_unwrap(node) {
+                next.extend(Self::unlink(&mut node.next));
+            }
+        }
+    }
+}
+
+impl<T> Node<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    ///
+    /// Nodes the given link has pointed to, if nothing else refers to the link.
+    fn unlink(link: &mut TVar<Link<T>>) -> Vec<Arc<Node<T>>> {
+        link.take_values(None)
+            .into_iter()
+            .filter_map(|link| (*link).clone())
+            .collect()
+    }
+}
+
+///
+/// Transactional FIFO queue.
+///
+/// Queue is a linked list of nodes, and every link is a transactional variable. Pushes only
+/// touch the tail and pops only touch the head, so producers and consumers conflict only when
+/// the queue is about to become empty.
+///
+/// Operations run in the given transaction and compose with any other transactional operation
+/// in it. Clones of a queue are handles to the same queue.
+#[derive(Clone)]
+pub struct TQueue<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    head: TVar<Link<T>>,
+    tail: TVar<Link<T>>,
+    /// Clock of the manager the nodes are bound to
+    clock: Arc<Clock>,
+}
+
+impl<T> TQueue<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    ///
+    /// Empty queue.
+    pub fn new() -> Self {
+        Self::with_clock(TxnManager::global().clock.clone())
+    }
+
+    ///
+    /// Empty queue bound to the given transaction manager.
+    pub fn with_manager(manager: &TxnManager) -> Self {
+        Self::with_clock(manager.clock.clone())
+    }
+
+    fn with_clock(clock: Arc<Clock>) -> Self {
+        Self {
+            head: TVar::with_clock(None, DEFAULT_TX_TIMEOUT, clock.clone()),
+            tail: TVar::with_clock(None, DEFAULT_TX_TIMEOUT, clock.clone()),
+            clock,
+        }
+    }
+
+    ///
+    /// Appends the given value to the back of the queue.
+    pub fn push(&self, t: &mut Txn, value: T) {
+        let node = Arc::new(Node {
+            value,
+            next: TVar::with_clock(None, DEFAULT_TX_TIMEOUT, self.clock.clone()),
+        });
+
+        match t.read(&self.tail) {
+            Some(last) => t.write(&mut last.next.clone(), Some(node.clone())),
+            None => t.write(&mut self.head.clone(), Some(node.clone())),
+        };
+        t.write(&mut self.tail.clone(), Some(node));
+    }
+
+    ///
+    /// Removes the value at the front of the queue, if there is any.
+    pub fn pop(&self, t: &mut Txn) -> Option<T> {
+        let first = t.read(&self.head)?;
+        let next = t.read(&first.next);
+
+        if next.is_none() {
+            t.write(&mut self.tail.clone(), None);
+        }
+        t.write(&mut self.head.clone(), next);
+
+        Some(first.value.clone())
+    }
+
+    ///
+    /// Value at the front of the queue, if there is any.
+    pub fn peek(&self, t: &mut Txn) -> Option<T> {
+        t.read(&self.head).map(|first| first.value.clone())
+    }
+
+    pub fn is_empty(&self, t: &mut Txn) -> bool {
+        t.read(&self.head).is_none()
+    }
+}
+
+impl<T> Default for TQueue<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Bounded transactional channel.
+///
+/// Sending to a full channel and receiving from an empty one return [TxnError::Retry], so a
+/// transaction initiated with [Txn::try_begin] that propagates it waits until the channel
+/// changes and runs again.
+///
+/// Senders count the free slots on their own and only take the slots freed by the receivers
+/// when they run out of them, so senders and receivers conflict only when the channel is about
+/// to become full or empty.
+#[derive(Clone)]
+pub struct TChan<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    queue: TQueue<T>,
+    capacity: usize,
+    /// Free slots known to the senders
+    room: TVar<usize>,
+    /// Slots freed by the receivers since the senders have taken them last
+    freed: TVar<usize>,
+}
+
+impl<T> TChan<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    ///
+    /// Empty channel which holds at most `capacity` values.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_clock(capacity, TxnManager::global().clock.clone())
+    }
+
+    ///
+    /// Empty channel bound to the given transaction manager.
+    pub fn with_manager(capacity: usize, manager: &TxnManager) -> Self {
+        Self::with_clock(capacity, manager.clock.clone())
+    }
+
+    fn with_clock(capacity: usize, clock: Arc<Clock>) -> Self {
+        Self {
+            room: TVar::with_clock(capacity, DEFAULT_TX_TIMEOUT, clock.clone()),
+            freed: TVar::with_clock(0, DEFAULT_TX_TIMEOUT, clock.clone()),
+            queue: TQueue::with_clock(clock),
+            capacity,
+        }
+    }
+
+    ///
+    /// Sends the given value, or returns [TxnError::Retry] if the channel is full.
+    pub fn send(&self, t: &mut Txn, value: T) -> TxnResult<()> {
+        let mut room = t.read(&self.room);
+        if room == 0 {
+            room = t.read(&self.freed);
+            if room == 0 {
+                return t.retry();
+            }
+            t.write(&mut self.freed.clone(), 0);
+        }
+
+        t.write(&mut self.room.clone(), room - 1);
+        self.queue.push(t, value);
+        Ok(())
+    }
+
+    ///
+    /// Receives the oldest value, or returns [TxnError::Retry] if the channel is empty.
+    pub fn recv(&self, t: &mut Txn) -> TxnResult<T> {
+        match self.try_recv(t) {
+            Some(value) => Ok(value),
+            None => t.retry(),
+        }
+    }
+
+    ///
+    /// Receives the oldest value, if there is any.
+    pub fn try_recv(&self, t: &mut Txn) -> Option<T> {
+        let value = self.queue.pop(t)?;
+        let freed = t.read(&self.freed);
+        t.write(&mut self.freed.clone(), freed + 1);
+
+        Some(value)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_empty(&self, t: &mut Txn) -> bool {
+        self.queue.is_empty(t)
+    }
+}
+
+#[cfg(test)]
+mod tqueue_tests {
+    use crate::txn::errors::*;
+    use crate::txn::fixture::txn;
+    use crate::txn::prelude::*;
+    use std::thread;
+
+    #[test]
+    fn tqueue_fifo() {
+        let queue = TQueue::new();
+
+        txn()
+            .begin(|t| (0..3).for_each(|v| queue.push(t, v)))
+            .unwrap();
+        let popped = txn()
+            .begin(|t| {
+                let first = queue.pop(t);
+                queue.push(t, 3);
+                (first, queue.peek(t))
+            })
+            .unwrap();
+        assert_eq!(popped, (Some(0), Some(1)));
+
+        let rest = txn()
+            .begin(|t| std::iter::from_fn(|| queue.pop(t)).collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(rest, vec![1, 2, 3]);
+        assert!(txn().begin(|t| queue.is_empty(t)).unwrap());
+
+        // Rolled back pushes leave the queue untouched.
+        let res = txn().try_begin(|t| {
+            queue.push(t, 4);
+            Err::<(), _>(TxnError::Abort)
+        });
+        assert!(res.is_err());
+        assert_eq!(txn().begin(|t| queue.pop(t)).unwrap(), None);
+    }
+
+    #[test]
+    fn tqueue_head_and_tail_do_not_conflict() {
+        let queue = TQueue::new();
+        txn()
+            .begin(|t| (0..3).for_each(|v| queue.push(t, v)))
+            .unwrap();
+
+        let mut ctx = txn().start().unwrap();
+        ctx.resume(|t| queue.push(t, 3)).unwrap();
+
+        // Consumer commits in between, the producer commits without a conflict.
+        assert_eq!(txn().begin(|t| queue.pop(t)).unwrap(), Some(0));
+        ctx.commit().unwrap();
+
+        let rest = txn()
+            .begin(|t| std::iter::from_fn(|| queue.pop(t)).collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(rest, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn tqueue_drop_long_queue() {
+        let queue = TQueue::new();
+        for _ in 0..2_000 {
+            txn()
+                .begin(|t| (0..100).for_each(|v| queue.push(t, v)))
+                .unwrap();
+        }
+
+        // Nodes are unlinked one by one instead of dropping the rest of the queue recursively.
+        drop(queue);
+    }
+
+    #[test]
+    fn tchan_bounded() {
+        let chan = TChan::new(2);
+        let txn = txn();
+
+        let receiver = {
+            let (chan, txn) = (chan.clone(), txn.clone());
+            thread::spawn(move || {
+                (0..10)
+                    .map(|_| txn.try_begin(|t| chan.recv(t)).unwrap())
+                    .collect::<Vec<u64>>()
+            })
+        };
+
+        for v in 0..10 {
+            txn.try_begin(|t| chan.send(t, v)).unwrap();
+        }
+
+        assert_eq!(receiver.join().unwrap(), (0..10).collect::<Vec<_>>());
+        assert!(txn.begin(|t| chan.is_empty(t)).unwrap());
+
+        // Full channel retries the sender.
+        txn.try_begin(|t| {
+            chan.send(t, 0)?;
+            chan.send(t, 1)
+        })
+        .unwrap();
+        let full = txn.try_begin(|t| {
+            let res = chan.send(t, 2);
+            Ok(matches!(res, Err(TxnError::Retry)))
+        });
+        assert!(full.unwrap());
+        assert_eq!(chan.capacity(), 2);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::txn::clock::Clock;
+use crate::txn::constants::DEFAULT_TX_TIMEOUT;
+use crate::txn::transact::{Txn, TxnManager};
+use crate::txn::vars::TVar;
+
+use std::any::Any;
+use std::sync::Arc;
+
+type Elements<T> = Arc<Vec<TVar<T>>>;
+
+///
+/// Transactional vector.
+///
+/// Every element is a transactional variable, and the vector holds them in a transactional
+/// variable of its own. Reading and writing the existing elements only touch their own
+/// variables, so they conflict with the transactions which access the same elements. Pushing
+/// and popping change the length, so they conflict with every access.
+///
+/// Pushing and popping copy the list of the element variables, which is `O(n)` in the length
+/// of the vector, though the elements themselves are not cloned. Vectors that grow or shrink
+/// often are better kept as a [TQueue](crate::txn::tqueue::TQueue) or a
+/// [TMap](crate::txn::tmap::TMap) keyed by the index.
+///
+/// Operations run in the given transaction and compose with any other transactional operation
+/// in it. Clones of a vector are handles to the same vector.
+#[derive(Clone)]
+pub struct TVec<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    elements: TVar<Elements<T>>,
+    /// Clock of the manager the elements are bound to
+    clock: Arc<Clock>,
+}
+
+impl<T> TVec<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    ///
+    /// Empty vector.
+    pub fn new() -> Self {
+        Self::with_clock(TxnManager::global().clock.clone())
+    }
+
+    ///
+    /// Empty vector bound to the given transaction manager.
+    pub fn with_manager(manager: &TxnManager) -> Self {
+        Self::with_clock(manager.clock.clone())
+    }
+
+    fn with_clock(clock: Arc<Clock>) -> Self {
+        Self {
+            elements: TVar::with_clock(Arc::new(Vec::new()), DEFAULT_TX_TIMEOUT, clock.clone()),
+            clock,
+        }
+    }
+
+    pub fn get(&self, t: &mut Txn, index: usize) -> Option<T> {
+        let elements = t.read(&self.elements);
+        elements.get(index).map(|element| t.read(element))
+    }
+
+    ///
+    /// Replaces the element at the given index, and returns the previous one. Returns `None`
+    /// and writes nothing if the index is out of bounds.
+    pub fn set(&self, t: &mut Txn, index: usize, value: T) -> Option<T> {
+        let elements = t.read(&self.elements);
+        let element = elements.get(index)?;
+
+        let previous = t.read(element);
+        t.write(&mut element.clone(), value);
+        Some(previous)
+    }
+
+    ///
+    /// Appends the given value to the back of the vector. Copies the list of the element
+    /// variables, see [TVec].
+    pub fn push(&self, t: &mut Txn, value: T) {
+        let mut elements = (*t.read(&self.elements)).clone();
+        elements.push(TVar::with_clock(
+            value,
+            DEFAULT_TX_TIMEOUT,
+            self.clock.clone(),
+        ));
+        t.write(&mut self.elements.clone(), Arc::new(elements));
+    }
+
+    ///
+    /// Removes the last element, if there is any. Copies the list of the element variables,
+    /// see [TVec].
+    pub fn pop(&self, t: &mut Txn) -> Option<T> {
+        let mut elements = (*t.read(&self.elements)).clone();
+        let last = elements.pop()?;
+
+        let value = t.read(&last);
+        t.write(&mut self.elements.clone(), Arc::new(elements));
+        Some(value)
+    }
+
+    pub fn len(&self, t: &mut Txn) -> usize {
+        t.read(&self.elements).len()
+    }
+
+    pub fn is_empty(&self, t: &mut Txn) -> bool {
+        self.len(t) == 0
+    }
+
+    pub fn to_vec(&self, t: &mut Txn) -> Vec<T> {
+        let elements = t.read(&self.elements);
+        elements.iter().map(|element| t.read(element)).collect()
+    }
+}
+
+impl<T> Default for TVec<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tvec_tests {
+    use crate::txn::fixture::txn;
+    use crate::txn::prelude::*;
+
+    #[test]
+    fn tvec_operations() {
+        let vec = TVec::new();
+
+        txn()
+            .begin(|t| (0..4_u64).for_each(|v| vec.push(t, v)))
+            .unwrap();
+        let changed = txn()
+            .begin(|t| (vec.set(t, 1, 10), vec.set(t, 4, 40), vec.pop(t)))
+            .unwrap();
+        assert_eq!(changed, (Some(1), None, Some(3)));
+
+        let read = txn()
+            .begin(|t| (vec.get(t, 1), vec.get(t, 3), vec.len(t), vec.to_vec(t)))
+            .unwrap();
+        assert_eq!(read, (Some(10), None, 3, vec![0, 10, 2]));
+        assert!(!txn().begin(|t| vec.is_empty(t)).unwrap());
+    }
+
+    #[test]
+    fn tvec_elements_do_not_conflict() {
+        let vec = TVec::new();
+        txn()
+            .begin(|t| (0..2_u64).for_each(|v| vec.push(t, v)))
+            .unwrap();
+
+        let mut ctx = txn().start().unwrap();
+        ctx.resume(|t| {
+            let v = vec.get(t, 0).unwrap();
+            vec.set(t, 0, v + 10);
+        })
+        .unwrap();
+
+        txn().begin(|t| vec.set(t, 1, 20)).unwrap();
+        ctx.commit().unwrap();
+
+        assert_eq!(txn().begin(|t| vec.to_vec(t)).unwrap(), vec![10, 20]);
+    }
+}
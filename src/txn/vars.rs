@@ -2,12 +2,11 @@ use std::{
     borrow::Borrow,
     cell::UnsafeCell,
     marker::PhantomData as marker,
-    ops::{Deref, DerefMut},
     sync::{
         atomic::{self, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use super::{
@@ -20,22 +19,34 @@ use log::*;
 
 use parking_lot::*;
 
-use super::utils;
-
-use crate::txn::transact::TransactionConcurrency;
+use crate::txn::chain::VersionChain;
+use crate::txn::clock::Clock;
+use crate::txn::contention::{Contender, Resolution};
+use crate::txn::lockset::{LockSet, Owner};
+use crate::txn::ssi::{SiReads, SsiState};
+use crate::txn::transact::{TransactionConcurrency, TransactionIsolation};
+use crate::txn::watch::{Watch, Watchers};
 use crate::txn::writeset::WriteSet;
 use std::alloc::{dealloc, Layout};
 use std::any::Any;
 
 ///
 /// Transactional variable
+///
+/// Clones of a transactional variable are handles to the same variable.
+/// Every clone sees the values committed through any other clone.
 #[derive(Clone)]
 pub struct TVar<T>
 where
     T: Clone + Any + Send + Sync,
 {
-    pub(crate) data: Var,
+    /// Value observed by this handle
+    pub(crate) data: Arc<T>,
+    /// Committed versions shared by all handles
+    pub(crate) chain: Arc<VersionChain<T>>,
     pub(crate) lock: Arc<ReentrantMutex<bool>>,
+    /// Transaction which holds the lock, cleared when the lock is released
+    owner: Owner,
     /// TVar ID
     pub(crate) id: u64,
     /// R/W Timestamp
@@ -43,6 +54,12 @@ where
     /// Revision of last modification on this key.
     pub(crate) modrev: u64,
     timeout: usize,
+    /// Serializable transactions that have read this variable
+    sireads: Arc<SiReads>,
+    /// Clock of the manager this variable is bound to
+    clock: Arc<Clock>,
+    /// Subscriptions to the commits of this variable
+    watchers: Arc<Watchers<T>>,
     marker: marker<T>,
 }
 
@@ -53,15 +70,7 @@ where
     ///
     /// Instantiates transactional variable for later use in a transaction.
     pub fn new(data: T) -> Self {
-        TVar {
-            data: Arc::new(data),
-            lock: Arc::new(ReentrantMutex::new(true)),
-            id: TxnManager::dispense_tvar_id(),
-            stamp: TxnManager::rts(),
-            modrev: TxnManager::rts(),
-            timeout: super::constants::DEFAULT_TX_TIMEOUT,
-            marker,
-        }
+        Self::new_with_timeout(data, super::constants::DEFAULT_TX_TIMEOUT)
     }
 
     ///
@@ -71,17 +80,52 @@ where
     /// Highly discouraged for the daily use unless you have various code paths that can
     /// interfere over the variable that you instantiate.
     pub fn new_with_timeout(data: T, timeout: usize) -> Self {
+        Self::with_clock(data, timeout, TxnManager::global().clock.clone())
+    }
+
+    pub(crate) fn with_clock(data: T, timeout: usize, clock: Arc<Clock>) -> Self {
+        let data = Arc::new(data);
+        // Read timestamp of the thread may belong to another clock.
+        let stamp = TxnManager::rts().min(clock.now());
+
         TVar {
-            data: Arc::new(data),
+            data: data.clone(),
+            chain: Arc::new(VersionChain::new(Committed {
+                stamp,
+                data,
+                writer: None,
+            })),
             lock: Arc::new(ReentrantMutex::new(true)),
-            id: TxnManager::dispense_tvar_id(),
-            stamp: TxnManager::rts(),
-            modrev: TxnManager::rts(),
+            owner: Arc::new(Mutex::new(None)),
+            id: clock.dispense_tvar_id(),
+            stamp,
+            modrev: stamp,
             timeout,
+            sireads: Arc::new(SiReads::new(clock.clone())),
+            clock,
+            watchers: Arc::new(Watchers::default()),
             marker,
         }
     }
 
+    ///
+    /// Takes the values of this variable out if this handle is the only one of it, and resets
+    /// it to the given value. Values are dropped by the caller instead of the handle.
+    pub(crate) fn take_values(&mut self, data: T) -> Vec<Arc<T>> {
+        let mut values = vec![std::mem::replace(&mut self.data, Arc::new(data))];
+
+        if Arc::get_mut(&mut self.chain).is_some() {
+            values.extend(self.chain.versions().into_iter().map(|v| v.data));
+            self.chain = Arc::new(VersionChain::new(Committed {
+                stamp: self.stamp,
+                data: self.data.clone(),
+                writer: None,
+            }));
+        }
+
+        values
+    }
+
     pub(crate) fn set_stamp(&mut self, stamp: u64) {
         self.stamp = stamp;
     }
@@ -96,74 +140,265 @@ where
     /// Beware that this will not give correct results any given point
     /// in time during the course of execution of a transaction.
     pub fn get_data(&self) -> T {
-        let val = self.data.clone();
+        (*self.committed().data).clone()
+    }
+
+    ///
+    /// Reads this variable in the ongoing transaction of the current thread, like [Txn::read],
+    /// without cloning the value. Outside of a transaction, the latest committed value is read.
+    pub fn get(&self) -> Arc<T> {
+        self.open_read_var()
+    }
+
+    ///
+    /// Subscribes to the commits that write this variable from now on, see [Watch].
+    pub fn watch(&self) -> Watch<T> {
+        self.watchers.subscribe()
+    }
+
+    ///
+    /// Latest committed version of this variable.
+    pub(crate) fn committed(&self) -> Committed<T> {
+        self.chain.latest()
+    }
+
+    ///
+    /// Handle which observes the latest committed version of this variable.
+    fn observe(&self) -> Self {
+        self.observing(self.committed())
+    }
+
+    ///
+    /// Handle which observes the version of this variable visible at the given timestamp, if it
+    /// is still retained.
+    fn observe_at(&self, ts: u64) -> Option<Self> {
+        self.chain.at(ts).map(|version| self.observing(version))
+    }
+
+    fn observing(&self, version: Committed<T>) -> Self {
+        let mut this = self.clone();
+        this.data = version.data;
+        this.set_stamp(version.stamp);
+        this.set_mod_rev(version.stamp);
+        this
+    }
+
+    ///
+    /// Panics if the given transaction is ongoing and its manager isn't the one this variable is
+    /// bound to, timestamps of the different managers aren't comparable.
+    fn check_bound(&self, txn: &Txn) {
+        if txn.is_active() {
+            assert!(
+                Arc::ptr_eq(&self.clock, &txn.clock),
+                "Variable is bound to another transaction manager."
+            );
+        }
+    }
 
-        (&*val as &dyn Any)
-            .downcast_ref::<T>()
-            .expect("Only tx vars are allowed for values.")
-            .clone()
+    ///
+    /// Lock timeout of this variable, variable's own timeout overrides the transaction's one.
+    fn lock_timeout(&self, txn: &Txn) -> Duration {
+        match self.timeout {
+            0 => Duration::from_millis(txn.timeout() as u64),
+            timeout => Duration::from_millis(timeout as u64),
+        }
+    }
+
+    ///
+    /// Takes the lock of this variable until the end of the transaction when the concurrency
+    /// control is pessimistic. Optimistic transactions only wait for the ongoing commit on it.
+    fn guard(&self, txn: &Txn) -> bool {
+        match txn.cc {
+            TransactionConcurrency::Pessimistic => self.acquire(txn, self.lock_timeout(txn)),
+            TransactionConcurrency::Optimistic => {
+                self.lock.try_lock_for(self.lock_timeout(txn)).is_some()
+            }
+        }
+    }
+
+    ///
+    /// Acquires the lock of this variable until the end of the transaction by waiting at most
+    /// `timeout`. Conflicts with the transaction holding the lock are resolved by the contention
+    /// manager of the transaction.
+    fn acquire(&self, txn: &Txn, timeout: Duration) -> bool {
+        let mut ls = LockSet::local();
+        let me = &txn.contender;
+        if ls.acquire_owned(
+            self.id,
+            &self.lock,
+            (&self.owner, me),
+            Duration::from_secs(0),
+        ) {
+            return true;
+        }
+
+        let deadline = Instant::now() + timeout;
+        me.wait(true);
+
+        let acquired = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // Aborted by some other transaction meanwhile.
+            if remaining == Duration::from_secs(0) || txn.is_marked_rollback() {
+                break false;
+            }
+
+            let enemy = self.owner.lock().clone();
+            let wait = match enemy {
+                Some(enemy) if !Arc::ptr_eq(&enemy, me) => {
+                    me.conflicted();
+                    me.wait_for(enemy.clone());
+                    match txn.cm.resolve(me, Some(&enemy)) {
+                        Resolution::Wait(wait) => wait,
+                        Resolution::Retry => break false,
+                        Resolution::AbortEnemy => {
+                            enemy.abort_with(format!("was aborted by txn #{}", me.birth()));
+                            Duration::from_millis(1)
+                        }
+                    }
+                }
+                _ => remaining,
+            };
+
+            if ls.acquire_owned(self.id, &self.lock, (&self.owner, me), wait.min(remaining)) {
+                break true;
+            }
+        };
+
+        me.wait(false);
+        acquired
     }
 
     pub(crate) fn open_read(&self) -> T {
+        (*self.open_read_var()).clone()
+    }
+
+    fn open_read_var(&self) -> Arc<T> {
         let rs = ReadSet::local();
         let txn = Txn::get_local();
-        let state: &TransactionState = &*txn.state.get();
+        let state: &TransactionState = &*txn.state();
+        self.check_bound(&txn);
 
         match state {
-            TransactionState::Committed | TransactionState::Unknown => self.get_data(),
+            TransactionState::Committed | TransactionState::Unknown => {
+                self.committed().data.clone()
+            }
+            TransactionState::Active if txn.read_only => {
+                // Snapshot at the read timestamp is consistent without any validation.
+                match self.chain.at(TxnManager::rts()) {
+                    Some(version) => version.data,
+                    None => {
+                        // Version is collected already, transaction needs a fresh snapshot.
+                        txn.rollback_execution();
+                        self.committed().data
+                    }
+                }
+            }
             TransactionState::Active => {
                 let ws = WriteSet::local();
+                txn.contender.opened();
 
-                let scratch = ws.get_by_stamp::<T>(self.stamp);
+                if let Some(written) = ws.get_by_id(self.id) {
+                    return Self::cell(written).data.clone();
+                }
 
-                if scratch.is_none() {
-                    if self.is_locked() {
-                        // TODO: throw abort
-                        txn.rollback();
-                        // panic!("READ: You can't lock and still continue processing");
-                    }
+                if let TransactionIsolation::ReadCommitted = txn.iso {
+                    // Latest committed value is read every time. Reads are tracked only to wait
+                    // for their changes on retry, they are never validated.
+                    let tvar = self.observe();
+                    let read = tvar.data.clone();
+                    txn.footprint.set_reads(rs.add(tvar.entry()));
 
-                    let tvar = self.clone();
-                    let arctvar = Arc::new(tvar);
-                    rs.add(arctvar);
+                    return read;
+                }
 
-                    self.get_data()
-                } else {
-                    let written = scratch.unwrap();
-                    let v: T = utils::version_to_dest(written);
-                    v
+                if let Some(read) = rs.get_by_id(self.id) {
+                    return Self::cell(read).data.clone();
                 }
+
+                if txn.is_snapshot() {
+                    return self.read_snapshot(&txn, rs);
+                }
+
+                if !self.guard(&txn) {
+                    // TODO: throw abort
+                    txn.rollback_execution();
+                    // panic!("READ: You can't lock and still continue processing");
+                    return self.committed().data.clone();
+                }
+
+                let tvar = self.observe();
+                if let TransactionConcurrency::Optimistic = txn.cc {
+                    // Committed after this transaction started, reads are consistent only if
+                    // nothing that is read so far has changed since.
+                    if tvar.stamp > TxnManager::rts() && !txn.extend(tvar.stamp) {
+                        txn.rollback_execution();
+                    }
+                }
+
+                let read = tvar.data.clone();
+                txn.footprint.set_reads(rs.add(tvar.entry()));
+
+                read
             }
             TransactionState::MarkedRollback => {
                 debug!("Starting rolling back: {}", TxnManager::rts());
                 txn.rolling_back();
-                txn.on_abort::<T>();
-                self.get_data()
+                txn.on_abort();
+                self.committed().data.clone()
             }
             TransactionState::RollingBack => {
                 // Give some time to recover and prevent inconsistency with giving only the pure
                 // data back.
                 // std::thread::sleep(Duration::from_millis(10));
-                self.get_data()
+                self.committed().data.clone()
             }
             TransactionState::Suspended => {
                 std::thread::sleep(Duration::from_millis(100));
-                self.get_data()
-            }
-            TransactionState::RolledBack => {
-                txn.rolled_back();
-                panic!("Transaction rollback finalized.");
+                self.committed().data.clone()
             }
-            s => {
-                panic!("Unexpected transaction state: {:?}", s);
+            // Execution is rolled back already or it is committing, it can't read anymore. It
+            // ends with [TxnError::Abort](crate::txn::errors::TxnError::Abort).
+            _ => {
+                txn.rollback();
+                self.committed().data.clone()
             }
         }
     }
 
     ///
-    /// Convenience over deref mut writes
-    pub(crate) fn open_write_deref_mut(&mut self) -> T {
-        self.open_write(self.get_data())
+    /// Reads the version visible at the read timestamp of the snapshot transaction. Serializable
+    /// transactions also record the rw-antidependencies to the writers of the newer versions.
+    fn read_snapshot(&self, txn: &Txn, rs: ReadSet) -> Arc<T> {
+        let rts = TxnManager::rts();
+        let ssi = txn.is_ssi();
+
+        // Registered before reading, so the writers that commit afterwards see this reader.
+        if ssi {
+            self.sireads.register(&txn.ssi);
+        }
+
+        match self.observe_at(rts) {
+            Some(tvar) => {
+                if ssi {
+                    let overwritten = self.chain.since(rts);
+                    if !overwritten.is_empty()
+                        && !txn.ssi.read_overwritten(&txn.commits.ssi, &overwritten)
+                    {
+                        txn.rollback_execution();
+                    }
+                }
+
+                let read = tvar.data.clone();
+                txn.footprint.set_reads(rs.add(tvar.entry()));
+
+                read
+            }
+            None => {
+                // Version is collected already, transaction needs a fresh snapshot.
+                txn.rollback_execution();
+                self.committed().data
+            }
+        }
     }
 
     ///
@@ -171,51 +406,67 @@ where
     pub(crate) fn open_write(&mut self, data: T) -> T {
         // dbg!("OPEN WRITE");
         let txn = Txn::get_local();
-        let state: &TransactionState = &*txn.state.get();
+        let state: &TransactionState = &*txn.state();
+        self.check_bound(&txn);
 
         match state {
             TransactionState::Committed | TransactionState::Unknown => self.get_data(),
+            TransactionState::Active if txn.read_only => {
+                panic!("Writes are not allowed in read-only transactions.");
+            }
             TransactionState::Active => {
                 let mut ws = WriteSet::local();
+                txn.contender.opened();
 
-                let this = Arc::new(self.clone());
-                if ws.get_by_stamp::<T>(this.stamp).is_none() {
-                    if self.is_locked() {
-                        // TODO: throw abort
-                        // panic!("WRITE: You can't lock and still continue processing");
-                        txn.rollback();
+                if ws.get_by_id(self.id).is_none() && !self.guard(&txn) {
+                    // TODO: throw abort
+                    // panic!("WRITE: You can't lock and still continue processing");
+                    txn.rollback_execution();
+                    return self.get_data();
+                }
+
+                // Snapshot transactions write over their snapshot, first committer wins.
+                let tvar = match txn.is_snapshot() {
+                    true => self.observe_at(TxnManager::rts()),
+                    false => Some(self.observe()),
+                };
+
+                match tvar {
+                    Some(mut tvar) => {
+                        tvar.data = Arc::new(data.clone());
+                        ws.put(tvar.entry());
+                        txn.footprint.set_writes(ws.len());
                     }
-                    self.modrev = self.modrev.saturating_add(1);
-                    let this = Arc::new(self.clone());
-                    ws.put::<T>(this, Arc::new(data.clone()));
-
-                    // match txn.iso {
-                    //     TransactionIsolation::ReadCommitted => {
-                    //         dbg!("READ_COMMITTED_COMING");
-                    //         if let Some(mut l) = GLOBAL_DELTAS.try_lock() {
-                    //             let this = Arc::new(self.clone());
-                    //             l.push(Version::Write(this));
-                    //         }
-                    //     },
-                    //     _ => {
-                    //         // todo!()
-                    //     }
-                    // }
-
-                    self.data = Arc::new(data.clone());
+                    None => txn.rollback_execution(),
                 }
-                self.data = Arc::new(data);
+
+                // match txn.iso {
+                //     TransactionIsolation::ReadCommitted => {
+                //         dbg!("READ_COMMITTED_COMING");
+                //         if let Some(mut l) = GLOBAL_DELTAS.try_lock() {
+                //             let this = Arc::new(self.clone());
+                //             l.push(Version::Write(this));
+                //         }
+                //     },
+                //     _ => {
+                //         // todo!()
+                //     }
+                // }
+
+                data
+            }
+            TransactionState::MarkedRollback => {
+                debug!("Starting rolling back: {}", TxnManager::rts());
+                txn.rolling_back();
+                txn.on_abort();
                 self.get_data()
             }
-            TransactionState::MarkedRollback
-            | TransactionState::RollingBack
-            | TransactionState::RolledBack => {
+            TransactionState::RollingBack | TransactionState::RolledBack => {
                 // TODO: Normally aborted, I am still unsure that should I represent this as
                 // full committed read or panic with a fault.
                 // According to science serializable systems get panicked here.
 
                 // panic!("Panic abort, no writes are possible.");
-                txn.state.replace_with(|_| TransactionState::Unknown);
                 self.get_data()
             }
             TransactionState::Suspended => {
@@ -228,13 +479,49 @@ where
         }
     }
 
-    pub(crate) fn validate(&self) -> bool {
+    ///
+    /// Entry of this handle in the read and write sets of the transaction.
+    fn entry(self) -> Entry {
+        Entry::new(self.id, self.stamp, Arc::new(self))
+    }
+
+    ///
+    /// Handle of the given entry, which belongs to a variable of this type.
+    pub(crate) fn cell(entry: &Entry) -> &Self {
+        let cell: &dyn Any = &*entry.cell;
+        cell.downcast_ref::<Self>()
+            .expect("Entry doesn't belong to a variable of this type.")
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        self.lock.try_lock().is_none()
+    }
+
+    pub(crate) fn is_not_locked_and_current(&self) -> bool {
+        !self.is_locked() && self.stamp == self.committed().stamp
+    }
+
+    pub(crate) fn is_writer_held_by_current_thread(&self) -> bool {
+        self.lock.is_owned_by_current_thread()
+    }
+}
+
+impl<T> TVarCell for TVar<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    fn validate(&self) -> bool {
+        if self.is_locked() && !self.is_writer_held_by_current_thread() {
+            // TODO: MSG: Currently locked
+            return false;
+        }
+
         let txn = Txn::get_local();
-        let state: &TransactionState = &*txn.state.get();
+        let state: &TransactionState = &*txn.state();
 
         match state {
             TransactionState::Committed | TransactionState::Unknown => true,
-            TransactionState::Active => {
+            TransactionState::Active | TransactionState::Preparing => {
                 let free = self.is_not_locked_and_current();
                 let pure = self.stamp <= TxnManager::rts();
 
@@ -249,32 +536,34 @@ where
         }
     }
 
-    pub(crate) fn is_locked(&self) -> bool {
-        self.lock.try_lock().is_none()
+    fn latest(&self) -> u64 {
+        self.committed().stamp
     }
 
-    pub(crate) fn is_not_locked_and_current(&self) -> bool {
-        !self.is_locked()
+    fn lock(&self, timeout: Duration) -> bool {
+        self.acquire(&Txn::get_local(), timeout)
     }
 
-    pub(crate) fn is_writer_held_by_current_thread(&self) -> bool {
-        self.is_locked()
+    fn publish(&self, stamp: u64, writer: Option<Arc<SsiState>>) {
+        let version = Committed {
+            stamp,
+            data: self.data.clone(),
+            writer,
+        };
+
+        self.chain.publish(version, self.clock.horizon());
     }
-}
 
-impl<T: Any + Clone + Send + Sync> Deref for TVar<T> {
-    type Target = T;
+    fn notify(&self, stamp: u64) {
+        self.watchers.notify(&self.data, stamp);
+    }
 
-    fn deref(&self) -> &T {
-        let x: *mut T = BoxMemory.allocate(self.open_read());
-        unsafe { &*(x) }
+    fn unlock(&self) {
+        LockSet::local().unlock(self.id);
     }
-}
 
-impl<T: 'static + Any + Clone + Send + Sync> DerefMut for TVar<T> {
-    fn deref_mut(&mut self) -> &mut T {
-        let x: *mut T = BoxMemory.allocate(self.open_write_deref_mut());
-        unsafe { &mut *(x) }
+    fn readers(&self) -> &SiReads {
+        &self.sireads
     }
 }
 
@@ -1,77 +1,115 @@
 use std::any::Any;
-use std::fmt;
-use std::fmt::Formatter;
-use std::hash::{Hash, Hasher};
-use std::marker::PhantomData as marker;
-use std::ptr::NonNull;
-use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
+use std::time::Duration;
 
-pub type Var = Arc<dyn Any + Send + Sync>;
+use crate::txn::ssi::{SiReads, SsiState};
 
-#[derive(Clone)]
-pub enum Version {
-    Read(Var),
-    Write(Var),
+///
+/// Committed value of a transactional variable along with the timestamp it was committed at.
+pub(crate) struct Committed<T> {
+    pub(crate) stamp: u64,
+    pub(crate) data: Arc<T>,
+    /// Serializable transaction that has committed this version.
+    pub(crate) writer: Option<Arc<SsiState>>,
 }
 
-impl Version {
-    pub fn extract(&self) -> &Var {
-        match self {
-            Version::Read(x) => x,
-            Version::Write(x) => x,
+impl<T> Clone for Committed<T> {
+    fn clone(&self) -> Self {
+        Self {
+            stamp: self.stamp,
+            data: self.data.clone(),
+            writer: self.writer.clone(),
         }
     }
+}
+
+///
+/// Transactional variable of any value type, along with the value a transaction observes in
+/// it. Value is the version that is read for the read set, and the value to publish for the
+/// write set. Cells are typed variables underneath, which are told apart as [Any].
+pub(crate) trait TVarCell: Any + Send + Sync {
+    ///
+    /// Checks that the observed version is still the latest and not locked by someone else.
+    fn validate(&self) -> bool;
+
+    ///
+    /// Timestamp of the latest committed version.
+    fn latest(&self) -> u64;
+
+    ///
+    /// Acquires the lock of the variable until the end of the transaction.
+    fn lock(&self, timeout: Duration) -> bool;
+
+    ///
+    /// Publishes the observed value as the latest committed version.
+    fn publish(&self, stamp: u64, writer: Option<Arc<SsiState>>);
 
-    pub fn read(&self) -> Var {
-        return match &*self {
-            &Version::Read(ref v) | &Version::Write(ref v) => v.clone(),
-        };
+    ///
+    /// Delivers the published value to the subscriptions of the variable.
+    fn notify(&self, stamp: u64);
+
+    ///
+    /// Releases the lock of the variable.
+    fn unlock(&self);
+
+    ///
+    /// Serializable transactions that have read the variable.
+    fn readers(&self) -> &SiReads;
+}
+
+///
+/// Transactional variable that is accessed in a transaction.
+///
+/// Read and write sets are made of entries, so variables of any type can participate in the
+/// same transaction.
+#[derive(Clone)]
+pub(crate) struct Entry {
+    /// TVar ID
+    pub(crate) id: u64,
+    /// Timestamp of the observed version.
+    pub(crate) stamp: u64,
+    /// Observed variable, real: TVar<T>
+    pub(crate) cell: Arc<dyn TVarCell>,
+}
+
+impl Entry {
+    pub(crate) fn new(id: u64, stamp: u64, cell: Arc<dyn TVarCell>) -> Self {
+        Self { id, stamp, cell }
     }
 
-    pub fn write(&mut self, w: Var) {
-        *self = match self.clone() {
-            Version::Write(_) => Version::Write(w),
-            // TODO: Not sure
-            _ => Version::Write(w),
-        };
+    pub(crate) fn validate(&self) -> bool {
+        self.cell.validate()
     }
-}
 
-impl fmt::Debug for Version {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Version").field("var", &self).finish()
+    ///
+    /// Is the observed version still the latest committed one.
+    pub(crate) fn is_current(&self) -> bool {
+        self.stamp == self.latest()
     }
-}
 
-impl Hash for Version {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        let x: ArcLayout<dyn Any + Send + Sync> = unsafe { std::mem::transmute_copy(&self.read()) };
+    pub(crate) fn lock(&self, timeout: Duration) -> bool {
+        self.cell.lock(timeout)
+    }
 
-        x.ptr.as_ptr().hash(state);
+    ///
+    /// Timestamp of the latest committed version.
+    pub(crate) fn latest(&self) -> u64 {
+        self.cell.latest()
     }
-}
 
-impl PartialEq for Version {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Version::Read(left), Version::Read(right)) => Arc::ptr_eq(&left, &right),
-            (Version::Write(left), Version::Write(right)) => Arc::ptr_eq(&left, &right),
-            _ => false,
-        }
+    pub(crate) fn publish(&self, stamp: u64, writer: Option<Arc<SsiState>>) {
+        self.cell.publish(stamp, writer)
     }
-}
 
-impl Eq for Version {}
+    pub(crate) fn notify(&self, stamp: u64) {
+        self.cell.notify(stamp)
+    }
 
-#[repr(C)]
-struct ArcInnerLayout<T: ?Sized> {
-    strong: AtomicUsize,
-    weak: AtomicUsize,
-    data: T,
-}
+    pub(crate) fn unlock(&self) {
+        self.cell.unlock()
+    }
 
-struct ArcLayout<T: ?Sized> {
-    ptr: NonNull<ArcInnerLayout<T>>,
-    phantom: marker<ArcInnerLayout<T>>,
+    pub(crate) fn readers(&self) -> &SiReads {
+        self.cell.readers()
+    }
 }
@@ -0,0 +1,1152 @@
+use crate::sync::atomics::AtomicBox;
+use crate::txn::vars::TVar;
+use crate::txn::version::Entry;
+
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const LOG_FILE: &str = "redo.log";
+const CHECKPOINT_FILE: &str = "checkpoint";
+const CHECKPOINT_TMP_FILE: &str = "checkpoint.tmp";
+/// Number of the execution ids reserved at once, see [Wal::reserve]
+const RESERVED_XIDS: u64 = 1 << 20;
+
+///
+/// Values which can be written to the redo log and read back on recovery.
+///
+/// Encoding only has to be read back by the same type, it isn't meant to be portable.
+pub trait Durable: Sized {
+    ///
+    /// Appends the encoded value to the given buffer.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    ///
+    /// Decodes a value from the front of the given buffer, and advances it past the value.
+    fn decode(buf: &mut &[u8]) -> io::Result<Self>;
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "Encoded value is truncated",
+        ));
+    }
+
+    let (front, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(front)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+macro_rules! durable_int {
+    ($($t:ty),*) => {
+        $(
+            impl Durable for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    let len = bytes.len();
+                    bytes.copy_from_slice(take(buf, len)?);
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+durable_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Durable for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(u64::decode(buf)? as usize)
+    }
+}
+
+impl Durable for isize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as i64).encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(i64::decode(buf)? as isize)
+    }
+}
+
+impl Durable for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("Encoded bool is neither 0 nor 1")),
+        }
+    }
+}
+
+impl Durable for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode(_buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl Durable for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = usize::decode(buf)?;
+        String::from_utf8(take(buf, len)?.to_vec())
+            .map_err(|_| invalid("Encoded string isn't UTF-8"))
+    }
+}
+
+impl<T: Durable> Durable for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.iter().for_each(|v| v.encode(buf));
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = usize::decode(buf)?;
+        // Length isn't trusted for the allocation, every element takes at least a byte.
+        let mut values = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            values.push(T::decode(buf)?);
+        }
+
+        Ok(values)
+    }
+}
+
+impl<T: Durable> Durable for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.is_some().encode(buf);
+        if let Some(v) = self {
+            v.encode(buf);
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match bool::decode(buf)? {
+            true => Ok(Some(T::decode(buf)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<T: Durable> Durable for Arc<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Arc::new(T::decode(buf)?))
+    }
+}
+
+impl<T: Durable> Durable for AtomicBox<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.get().encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(AtomicBox::new(T::decode(buf)?))
+    }
+}
+
+impl<K, V, S> Durable for HashMap<K, V, S>
+where
+    K: Durable + Eq + Hash,
+    V: Durable,
+    S: BuildHasher + Default,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.iter().for_each(|(k, v)| {
+            k.encode(buf);
+            v.encode(buf);
+        });
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = usize::decode(buf)?;
+        let mut entries = HashMap::with_capacity_and_hasher(len.min(buf.len()), S::default());
+        for _ in 0..len {
+            let (k, v) = Durable::decode(buf)?;
+            entries.insert(k, v);
+        }
+
+        Ok(entries)
+    }
+}
+
+impl<A: Durable, B: Durable> Durable for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok((A::decode(buf)?, B::decode(buf)?))
+    }
+}
+
+impl<A: Durable, B: Durable, C: Durable> Durable for (A, B, C) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+        self.2.encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok((A::decode(buf)?, B::decode(buf)?, C::decode(buf)?))
+    }
+}
+
+///
+/// When the commits wait for the redo log to reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Commits are written to the log but never synced, the OS flushes them eventually. They
+    /// survive a crash of the process but not of the machine.
+    Never,
+    /// Every commit syncs the log before it returns.
+    #[default]
+    Always,
+    /// Commits wait for a shared sync of the log. The first commit that waits syncs the log
+    /// after the given delay, on behalf of every commit that is logged until then.
+    Group {
+        /// Time to wait for the other commits before syncing
+        delay: Duration,
+    },
+}
+
+///
+/// Change logged to the redo log.
+enum Record {
+    /// Committed values of the registered variables written by a transaction
+    /// along with its commit decision, if it has participants
+    Commit {
+        stamp: u64,
+        writes: Vec<(String, Vec<u8>)>,
+        decision: Option<(u64, usize)>,
+    },
+    /// Participant has learned the commit decision of the execution
+    Acknowledge { xid: u64 },
+    /// Execution ids below the given one may be in use
+    Reserve { xids: u64 },
+}
+
+impl Durable for Record {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Record::Commit {
+                stamp,
+                writes,
+                decision,
+            } => {
+                0_u8.encode(buf);
+                stamp.encode(buf);
+                writes.encode(buf);
+                decision.encode(buf);
+            }
+            Record::Acknowledge { xid } => {
+                1_u8.encode(buf);
+                xid.encode(buf);
+            }
+            Record::Reserve { xids } => {
+                2_u8.encode(buf);
+                xids.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Record::Commit {
+                stamp: u64::decode(buf)?,
+                writes: Vec::decode(buf)?,
+                decision: Option::decode(buf)?,
+            }),
+            1 => Ok(Record::Acknowledge {
+                xid: u64::decode(buf)?,
+            }),
+            2 => Ok(Record::Reserve {
+                xids: u64::decode(buf)?,
+            }),
+            _ => Err(invalid("Unknown redo log record")),
+        }
+    }
+}
+
+///
+/// FNV-1a checksum of the framed records.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |h, b| {
+        (h ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+///
+/// Frame of a record: length, checksum and the encoded record. Frames which are torn by a
+/// crash are told apart with the checksum.
+///
+/// Length is 32 bits, records which don't fit in it are rejected.
+fn frame<T: Durable>(record: &T) -> io::Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    record.encode(&mut payload);
+
+    let len = u32::try_from(payload.len()).map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Record of {} bytes is larger than 4 GiB", payload.len()),
+        )
+    })?;
+
+    let mut buf = Vec::with_capacity(payload.len() + 8);
+    len.encode(&mut buf);
+    checksum(&payload).encode(&mut buf);
+    buf.extend_from_slice(&payload);
+    Ok(buf)
+}
+
+///
+/// Reads the frame at the front of the given buffer. Returns `None` if the frame is torn.
+fn unframe<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let mut rest = *buf;
+    let len = u32::decode(&mut rest).ok()? as usize;
+    let sum = u32::decode(&mut rest).ok()?;
+    let payload = take(&mut rest, len).ok()?;
+
+    if checksum(payload) != sum {
+        return None;
+    }
+
+    *buf = rest;
+    Some(payload)
+}
+
+///
+/// Commit decisions which aren't acknowledged by all of their participants yet, along with the
+/// execution ids which are reserved.
+#[derive(Default)]
+struct Decided {
+    /// Execution ids below it may be prepared by the participants
+    xids: u64,
+    /// Number of the participants which haven't acknowledged, by execution ids
+    pending: HashMap<u64, usize>,
+}
+
+impl Decided {
+    fn apply(&mut self, record: &Record) {
+        match record {
+            Record::Commit {
+                decision: Some((xid, participants)),
+                ..
+            } => {
+                self.pending.insert(*xid, *participants);
+            }
+            Record::Commit { .. } => {}
+            Record::Acknowledge { xid } => {
+                if let Some(pending) = self.pending.get_mut(xid) {
+                    *pending = pending.saturating_sub(1);
+                    if *pending == 0 {
+                        self.pending.remove(xid);
+                    }
+                }
+            }
+            Record::Reserve { xids } => self.xids = self.xids.max(*xids),
+        }
+    }
+}
+
+///
+/// State rebuilt from the checkpoint and the log.
+#[derive(Default)]
+struct Recovered {
+    /// Latest commit timestamp
+    stamp: u64,
+    vars: HashMap<String, Vec<u8>>,
+    decided: Decided,
+}
+
+impl Recovered {
+    fn apply(&mut self, record: Record) {
+        self.decided.apply(&record);
+        if let Record::Commit { stamp, writes, .. } = record {
+            self.stamp = self.stamp.max(stamp);
+            self.vars.extend(writes);
+        }
+    }
+}
+
+/// Checkpoint: latest commit timestamp, encoded values of the variables by their names, and
+/// the reserved execution ids along with the pending decisions
+type Snapshot = (u64, Vec<(String, Vec<u8>)>, (u64, Vec<(u64, usize)>));
+
+///
+/// Variable whose commits are logged.
+struct Registered {
+    name: String,
+    /// Encodes the value of a write set entry of the variable
+    encode: fn(&Entry, &mut Vec<u8>),
+}
+
+struct Log {
+    file: File,
+    /// Number of the records appended so far
+    lsn: u64,
+    /// Latest commit timestamp which is logged
+    stamp: u64,
+    /// Latest logged values of the variables by their names, including the recovered ones
+    vars: HashMap<String, Vec<u8>>,
+    decided: Decided,
+}
+
+#[derive(Default)]
+struct Synced {
+    /// Number of the records which are on the disk
+    lsn: u64,
+    /// Some commit is syncing the log on behalf of the others
+    syncing: bool,
+}
+
+///
+/// Redo log of a [durable](crate::txn::transact::TxnManager::durable) transaction manager.
+///
+/// Registered variables are logged by their names, since their ids don't survive a restart.
+/// Every commit which writes any of them appends a record of its write timestamp and the
+/// values it has written. Record is appended once the commit is published and before its
+/// variables are unlocked, so the commits writing the same variable are logged in the order
+/// they are published. Durable tables are logged the same way, their frames are registered
+/// variables.
+///
+/// Commit decisions of the two-phase commits are logged in the commit records, and the
+/// participants acknowledging them are logged as they do. Decisions which aren't acknowledged
+/// by all of their participants are recovered, along with the execution ids which may be in
+/// use by the participants, so the ids aren't dispensed again.
+///
+/// Checkpoint writes the latest values of everything that is logged, and empties the log.
+/// Values are the logged ones rather than the published ones, so a commit that is published
+/// but not logged yet isn't in the checkpoint in part, it is logged after it.
+/// Recovery loads the latest checkpoint, replays the log on it and discards the records that
+/// are torn by a crash.
+///
+/// Failing to write or sync the log stops it, since the records after a missing one can't be
+/// recovered. Commit that has failed is visible already, so it only reports the error. Later
+/// commits which would be logged are refused before they are published, and checkpoints fail.
+pub(crate) struct Wal {
+    dir: PathBuf,
+    policy: SyncPolicy,
+    log: Mutex<Log>,
+    /// Handle of the log file which is synced without holding the log
+    sync_file: File,
+    synced: Mutex<Synced>,
+    cvar: Condvar,
+    /// Registered variables by their ids
+    vars: RwLock<HashMap<u64, Registered>>,
+    /// Error that has stopped the log, if it is stopped
+    failure: Mutex<Option<String>>,
+}
+
+impl Wal {
+    ///
+    /// Opens the redo log in the given directory, and recovers the state that is logged in it.
+    pub(crate) fn open<P: AsRef<Path>>(dir: P, policy: SyncPolicy) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut recovered = Recovered::default();
+        match fs::read(dir.join(CHECKPOINT_FILE)) {
+            Ok(bytes) => {
+                let mut buf = bytes.as_slice();
+                let mut payload =
+                    unframe(&mut buf).ok_or_else(|| invalid("Checkpoint is corrupted"))?;
+                let (stamp, vars, (xids, pending)): Snapshot = Durable::decode(&mut payload)?;
+
+                recovered.stamp = stamp;
+                recovered.vars = vars.into_iter().collect();
+                recovered.decided = Decided {
+                    xids,
+                    pending: pending.into_iter().collect(),
+                };
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG_FILE))?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut buf = bytes.as_slice();
+        let mut lsn = 0;
+        while let Some(mut payload) = unframe(&mut buf) {
+            recovered.apply(Record::decode(&mut payload)?);
+            lsn += 1;
+        }
+
+        // Torn records of the crash are cut off, so the new ones follow the intact ones.
+        if !buf.is_empty() {
+            file.set_len((bytes.len() - buf.len()) as u64)?;
+            file.sync_all()?;
+        }
+
+        Ok(Self {
+            dir,
+            policy,
+            sync_file: file.try_clone()?,
+            log: Mutex::new(Log {
+                file,
+                lsn,
+                stamp: recovered.stamp,
+                vars: recovered.vars,
+                decided: recovered.decided,
+            }),
+            synced: Mutex::new(Synced {
+                lsn,
+                syncing: false,
+            }),
+            cvar: Condvar::new(),
+            vars: RwLock::new(HashMap::new()),
+            failure: Mutex::new(None),
+        })
+    }
+
+    ///
+    /// Latest commit timestamp that is recovered.
+    pub(crate) fn stamp(&self) -> u64 {
+        self.log.lock().stamp
+    }
+
+    ///
+    /// Logs the commits of the given variable from now on, under the given name.
+    pub(crate) fn register<T>(&self, name: &str, tvar: &TVar<T>) -> io::Result<()>
+    where
+        T: Durable + Clone + Any + Send + Sync,
+    {
+        let mut vars = self.vars.write();
+        if vars.values().any(|var| var.name == name) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Variable `{}` is registered already", name),
+            ));
+        }
+
+        vars.insert(
+            tvar.id,
+            Registered {
+                name: name.into(),
+                encode: |entry, buf| TVar::<T>::cell(entry).data.encode(buf),
+            },
+        );
+
+        Ok(())
+    }
+
+    ///
+    /// Is any of the variables with the given ids registered.
+    pub(crate) fn logs(&self, mut ids: impl Iterator<Item = u64>) -> bool {
+        let vars = self.vars.read();
+        ids.any(|id| vars.contains_key(&id))
+    }
+
+    ///
+    /// Value logged under the given variable name, if there is any.
+    pub(crate) fn recover<T: Durable>(&self, name: &str) -> io::Result<Option<T>> {
+        match self.log.lock().vars.get(name) {
+            Some(bytes) => T::decode(&mut bytes.as_slice()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    ///
+    /// Reserved execution ids and the pending commit decisions.
+    pub(crate) fn decided(&self) -> (u64, HashMap<u64, usize>) {
+        let log = self.log.lock();
+        (log.decided.xids, log.decided.pending.clone())
+    }
+
+    ///
+    /// Logs the registered variables among the given write set entries, which are published at
+    /// the given timestamp, along with the commit decision of the given execution id for the
+    /// given number of participants. Returns the log sequence number of the record to
+    /// [sync](Wal::sync), or `None` if there is nothing to log.
+    pub(crate) fn commit(
+        &self,
+        stamp: u64,
+        writes: &[Entry],
+        decision: Option<(u64, usize)>,
+    ) -> io::Result<Option<u64>> {
+        let writes: Vec<(String, Vec<u8>)> = {
+            let vars = self.vars.read();
+            writes
+                .iter()
+                .filter_map(|entry| {
+                    let var = vars.get(&entry.id)?;
+                    let mut value = Vec::new();
+                    (var.encode)(entry, &mut value);
+                    Some((var.name.clone(), value))
+                })
+                .collect()
+        };
+
+        if writes.is_empty() && decision.is_none() {
+            return Ok(None);
+        }
+
+        let record = Record::Commit {
+            stamp,
+            writes,
+            decision,
+        };
+        self.append(&record).map(Some)
+    }
+
+    ///
+    /// Logs that a participant has learned the commit decision of the given execution id.
+    /// Record isn't synced, losing it only keeps the decision until the participant recovers.
+    pub(crate) fn acknowledge(&self, xid: u64) -> io::Result<()> {
+        self.append(&Record::Acknowledge { xid }).map(|_| ())
+    }
+
+    ///
+    /// Makes sure the given execution id isn't dispensed again after a restart, before any
+    /// participant is prepared with it. Ids are reserved in batches, so only the first
+    /// execution of a batch waits for the log.
+    pub(crate) fn reserve(&self, xid: u64) -> io::Result<()> {
+        if xid < self.log.lock().decided.xids {
+            return Ok(());
+        }
+
+        let lsn = self.append(&Record::Reserve {
+            xids: xid.saturating_add(RESERVED_XIDS),
+        })?;
+        self.sync(lsn)
+    }
+
+    ///
+    /// Appends the given record to the log. Returns the log sequence number of the record.
+    fn append(&self, record: &Record) -> io::Result<u64> {
+        // Commit that can't be logged leaves a gap, the records after it can't be recovered.
+        let bytes = frame(record).map_err(|e| self.stop("written", e))?;
+
+        let mut log = self.log.lock();
+        self.check()?;
+        log.file
+            .write_all(&bytes)
+            .map_err(|e| self.stop("written", e))?;
+
+        if let Record::Commit { stamp, writes, .. } = record {
+            log.stamp = log.stamp.max(*stamp);
+            log.vars.extend(writes.iter().cloned());
+        }
+        log.decided.apply(record);
+        log.lsn += 1;
+        Ok(log.lsn)
+    }
+
+    ///
+    /// Stops the log with the given error, which has happened while the log was being written
+    /// or synced as told.
+    fn stop(&self, action: &str, e: io::Error) -> io::Error {
+        let failure = format!("Redo log can't be {}: {}", action, e);
+        self.failure.lock().get_or_insert(failure.clone());
+        io::Error::new(e.kind(), failure)
+    }
+
+    ///
+    /// Fails if the log is stopped by an error.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        match &*self.failure.lock() {
+            Some(failure) => Err(io::Error::other(failure.clone())),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// Waits until the record with the given log sequence number is on the disk, as the
+    /// [SyncPolicy] requires.
+    pub(crate) fn sync(&self, lsn: u64) -> io::Result<()> {
+        let delay = match self.policy {
+            SyncPolicy::Never => return Ok(()),
+            SyncPolicy::Always => Duration::default(),
+            SyncPolicy::Group { delay } => delay,
+        };
+
+        let mut synced = self.synced.lock();
+        while synced.lsn < lsn {
+            // Records which aren't synced before the log is stopped may be lost.
+            self.check()?;
+
+            if synced.syncing {
+                self.cvar.wait(&mut synced);
+                continue;
+            }
+
+            synced.syncing = true;
+            let res = MutexGuard::unlocked(&mut synced, || {
+                if delay > Duration::default() {
+                    thread::sleep(delay);
+                }
+
+                // Every record up to here is written already, so the sync covers them.
+                let lsn = self.log.lock().lsn;
+                self.sync_file.sync_data().map(|_| lsn)
+            });
+            synced.syncing = false;
+            self.cvar.notify_all();
+
+            match res {
+                Ok(lsn) => synced.lsn = synced.lsn.max(lsn),
+                Err(e) => return Err(self.stop("synced", e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Writes the latest logged values of the variables to a new checkpoint, then empties the
+    /// log.
+    pub(crate) fn checkpoint(&self) -> io::Result<()> {
+        // Records wait until the checkpoint is taken, so everything logged is in it, and the
+        // commits which aren't logged yet are logged after it.
+        let log = self.log.lock();
+        self.check()?;
+
+        let vars = log
+            .vars
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let decided = &log.decided;
+        let pending = decided.pending.iter().map(|(xid, p)| (*xid, *p)).collect();
+        let checkpoint: Snapshot = (log.stamp, vars, (decided.xids, pending));
+        let tmp = self.dir.join(CHECKPOINT_TMP_FILE);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&frame(&checkpoint)?)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, self.dir.join(CHECKPOINT_FILE))?;
+        File::open(&self.dir)?.sync_all()?;
+
+        // Crashing before the log is emptied replays the log on the new checkpoint, which
+        // yields the same state.
+        log.file.set_len(0)?;
+        log.file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod wal_tests {
+    use crate::table::lotable::LOTable;
+    use crate::txn::errors::*;
+    use crate::txn::fixture::txn_in;
+    use crate::txn::prelude::*;
+    use parking_lot::Mutex;
+    use std::collections::BTreeSet;
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lever-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn wal_recovery() {
+        let dir = dir("wal_recovery");
+
+        {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+            let mut balance = manager.durable_tvar("balance", 100_u64).unwrap();
+            let mut owner = manager.durable_tvar("owner", String::new()).unwrap();
+            let mut scratch = manager.tvar(0_u64);
+            let users: LOTable<u64, String> = LOTable::durable(&manager, "users").unwrap();
+
+            let txn = txn_in(&manager);
+            txn.begin(|t| {
+                t.write(&mut balance, 150);
+                t.write(&mut owner, "lever".to_string());
+                t.write(&mut scratch, 1);
+            })
+            .unwrap();
+            txn.begin(|t| {
+                let v = t.read(&balance);
+                t.write(&mut balance, v + 50);
+            })
+            .unwrap();
+
+            users.insert(1, "one".into()).unwrap();
+            users.insert(2, "two".into()).unwrap();
+            users.remove(&1).unwrap();
+
+            // Names are unique.
+            assert!(manager.durable_tvar("balance", 0_u64).is_err());
+            assert!(LOTable::<u64, String>::durable(&manager, "users").is_err());
+        }
+
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        let balance = manager.durable_tvar("balance", 0_u64).unwrap();
+        let owner = manager.durable_tvar("owner", String::new()).unwrap();
+        let fresh = manager.durable_tvar("fresh", 7_u64).unwrap();
+        let users: LOTable<u64, String> = LOTable::durable(&manager, "users").unwrap();
+
+        assert_eq!(
+            txn_in(&manager)
+                .begin(|t| (t.read(&balance), t.read(&owner), t.read(&fresh)))
+                .unwrap(),
+            (200, "lever".to_string(), 7)
+        );
+        assert_eq!(users.get(&1), None);
+        assert_eq!(users.get(&2), Some("two".to_string()));
+
+        // Non-durable managers have no log.
+        assert!(TxnManager::global().durable_tvar("x", 0_u64).is_err());
+        assert!(TxnManager::global().checkpoint().is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wal_table_commits() {
+        let dir = dir("wal_table_commits");
+
+        {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+            let mut balance = manager.durable_tvar("balance", 0_u64).unwrap();
+            let users: LOTable<u64, String> = LOTable::durable(&manager, "users").unwrap();
+
+            // Table changes are part of the transaction they are made in.
+            let res = txn_in(&manager).try_begin(|t| {
+                t.write(&mut balance, 10);
+                users.insert(1, "one".into()).unwrap();
+                Err::<(), _>(TxnError::Abort)
+            });
+            assert!(res.is_err());
+            assert_eq!(users.get(&1), None);
+
+            txn_in(&manager)
+                .begin(|t| {
+                    t.write(&mut balance, 20);
+                    users.insert(2, "two".into()).unwrap();
+                })
+                .unwrap();
+        }
+
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        let balance = manager.durable_tvar("balance", 0_u64).unwrap();
+        let users: LOTable<u64, String> = LOTable::durable(&manager, "users").unwrap();
+
+        assert_eq!(balance.get_data(), 20);
+        assert_eq!(users.get(&1), None);
+        assert_eq!(users.get(&2), Some("two".to_string()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[derive(Default)]
+    struct Resource {
+        prepared: Mutex<BTreeSet<u64>>,
+        available: AtomicBool,
+        log: Mutex<Vec<(&'static str, u64)>>,
+    }
+
+    impl TxnParticipant for Resource {
+        fn prepare(&self, xid: u64) -> TxnResult<()> {
+            self.prepared.lock().insert(xid);
+            Ok(())
+        }
+
+        fn commit(&self, xid: u64) -> TxnResult<()> {
+            if !self.available.load(Ordering::SeqCst) {
+                return Err(TxnError::AbortWithContext("unavailable".into()));
+            }
+
+            self.prepared.lock().remove(&xid);
+            self.log.lock().push(("commit", xid));
+            Ok(())
+        }
+
+        fn rollback(&self, xid: u64) -> TxnResult<()> {
+            self.prepared.lock().remove(&xid);
+            self.log.lock().push(("rollback", xid));
+            Ok(())
+        }
+
+        fn in_doubt(&self) -> Vec<u64> {
+            self.prepared.lock().iter().copied().collect()
+        }
+    }
+
+    #[test]
+    fn wal_decisions() {
+        let dir = dir("wal_decisions");
+        let resource = Arc::new(Resource::default());
+
+        let xid = {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+            let mut counter = manager.durable_tvar("counter", 0_u64).unwrap();
+
+            let xid = txn_in(&manager)
+                .begin(|t| {
+                    t.enlist(resource.clone());
+                    t.write(&mut counter, 1);
+                    t.xid()
+                })
+                .unwrap();
+
+            // Pending decisions survive the checkpoints.
+            manager.checkpoint().unwrap();
+            xid
+        };
+        assert_eq!(resource.in_doubt(), vec![xid]);
+        resource.available.store(true, Ordering::SeqCst);
+
+        {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+
+            // Execution ids the participants may hold aren't dispensed again.
+            assert!(txn_in(&manager).begin(|t| t.xid()).unwrap() > xid);
+
+            manager.recover(&*resource).unwrap();
+            assert_eq!(*resource.log.lock(), vec![("commit", xid)]);
+        }
+
+        // Acknowledged decisions are forgotten.
+        resource.prepared.lock().insert(xid);
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        manager.recover(&*resource).unwrap();
+        assert_eq!(
+            *resource.log.lock(),
+            vec![("commit", xid), ("rollback", xid)]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wal_stopped_by_failure() {
+        let dir = dir("wal_stopped");
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        let mut counter = manager.durable_tvar("counter", 0_u64).unwrap();
+        let mut scratch = manager.tvar(0_u64);
+
+        let txn = txn_in(&manager);
+        txn.begin(|t| t.write(&mut counter, 1)).unwrap();
+
+        let wal = manager.wal().unwrap();
+        wal.stop("written", io::Error::other("disk is gone"));
+
+        // Commits which aren't logged go on.
+        txn.begin(|t| t.write(&mut scratch, 1)).unwrap();
+        assert_eq!(scratch.get_data(), 1);
+        let res = txn.begin(|t| {
+            t.write(&mut scratch, 2);
+            t.write(&mut counter, 2);
+        });
+        assert!(matches!(res, Err(TxnError::AbortWithContext(_))));
+        assert_eq!(scratch.get_data(), 1);
+
+        // Commits aren't published once the log is stopped, and they leave nothing locked.
+        let res = txn.begin(|t| t.write(&mut counter, 2));
+        assert!(matches!(res, Err(TxnError::AbortWithContext(_))));
+        assert_eq!(counter.get_data(), 1);
+        assert!(!counter.is_locked());
+        assert_eq!(txn.begin(|t| t.read(&counter)).unwrap(), 1);
+
+        assert!(wal.sync(wal.log.lock().lsn + 1).is_err());
+        assert!(manager.checkpoint().is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wal_checkpoint_and_torn_tail() {
+        let dir = dir("wal_checkpoint");
+
+        {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Never).unwrap();
+            let mut counter = manager.durable_tvar("counter", 0_u64).unwrap();
+            let mut other = manager.durable_tvar("other", Some(1_i32)).unwrap();
+            let users: LOTable<String, u64> = LOTable::durable(&manager, "users").unwrap();
+
+            let txn = txn_in(&manager);
+            txn.begin(|t| t.write(&mut counter, 1)).unwrap();
+            users.insert("one".into(), 1).unwrap();
+            manager.checkpoint().unwrap();
+
+            txn.begin(|t| t.write(&mut counter, 2)).unwrap();
+            users.clear();
+            users.insert("two".into(), 2).unwrap();
+            txn.begin(|t| t.write(&mut other, None)).unwrap();
+        }
+
+        // Crash has torn the last record.
+        OpenOptions::new()
+            .append(true)
+            .open(dir.join("redo.log"))
+            .unwrap()
+            .write_all(&[42, 0, 0, 0, 1, 2, 3])
+            .unwrap();
+
+        {
+            let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+            let mut counter = manager.durable_tvar("counter", 0_u64).unwrap();
+            let users: LOTable<String, u64> = LOTable::durable(&manager, "users").unwrap();
+
+            assert_eq!(counter.get_data(), 2);
+            assert_eq!(users.keys().collect::<Vec<_>>(), vec!["two".to_string()]);
+
+            // Unregistered variables survive the checkpoints, new records follow the intact ones.
+            manager.checkpoint().unwrap();
+            txn_in(&manager)
+                .begin(|t| t.write(&mut counter, 3))
+                .unwrap();
+        }
+
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        let counter = manager.durable_tvar("counter", 0_u64).unwrap();
+        let other = manager.durable_tvar("other", Some(0_i32)).unwrap();
+        assert_eq!((counter.get_data(), other.get_data()), (3, None));
+
+        // Clock goes on from the recovered commits.
+        let mut fresh = manager.tvar(0_u64);
+        txn_in(&manager).begin(|t| t.write(&mut fresh, 1)).unwrap();
+        assert!(fresh.committed().stamp > counter.committed().stamp);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wal_group_commit() {
+        let dir = dir("wal_group_commit");
+        let policy = SyncPolicy::Group {
+            delay: Duration::from_millis(1),
+        };
+
+        {
+            let manager = TxnManager::durable(&dir, policy).unwrap();
+            let counter = manager.durable_tvar("counter", 0_u64).unwrap();
+            let txn = txn_in(&manager);
+
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    let (mut counter, txn) = (counter.clone(), txn.clone());
+                    thread::spawn(move || {
+                        for _ in 0..25 {
+                            txn.begin(|t| {
+                                let v = t.read(&counter);
+                                t.write(&mut counter, v + 1);
+                            })
+                            .unwrap();
+                        }
+                    })
+                })
+                .collect();
+
+            for t in threads.into_iter() {
+                t.join().unwrap();
+            }
+        }
+
+        let manager = TxnManager::durable(&dir, policy).unwrap();
+        let counter = manager.durable_tvar("counter", 0_u64).unwrap();
+        assert_eq!(counter.get_data(), 100);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wal_checkpoint_during_commits() {
+        let dir = dir("wal_checkpoint_during_commits");
+        let manager = TxnManager::durable(&dir, SyncPolicy::Never).unwrap();
+        let x = manager.durable_tvar("x", 0_u64).unwrap();
+        let y = manager.durable_tvar("y", 0_u64).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let (mut x, mut y, txn, done) = (x.clone(), y.clone(), txn_in(&manager), done.clone());
+            thread::spawn(move || {
+                for i in 1..=20_000_u64 {
+                    txn.begin(|t| {
+                        t.write(&mut x, i);
+                        t.write(&mut y, i);
+                    })
+                    .unwrap();
+                }
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+
+        // Checkpoints never hold a commit which is published only in part, even if the log
+        // after them is lost.
+        let mut checkpoints = 0;
+        while !done.load(Ordering::SeqCst) || checkpoints == 0 {
+            manager.checkpoint().unwrap();
+            checkpoints += 1;
+
+            let bytes = fs::read(dir.join("checkpoint")).unwrap();
+            let mut buf = bytes.as_slice();
+            let mut payload = super::unframe(&mut buf).unwrap();
+            let (_, vars, _): super::Snapshot = super::Durable::decode(&mut payload).unwrap();
+            let value = |name: &str| {
+                vars.iter()
+                    .find(|(n, _)| n == name)
+                    .map_or(0, |(_, v)| u64::decode(&mut v.as_slice()).unwrap())
+            };
+            assert_eq!(value("x"), value("y"));
+        }
+
+        writer.join().unwrap();
+        manager.checkpoint().unwrap();
+        drop((x, y, manager));
+
+        let manager = TxnManager::durable(&dir, SyncPolicy::Always).unwrap();
+        let x = manager.durable_tvar("x", 0_u64).unwrap();
+        let y = manager.durable_tvar("y", 0_u64).unwrap();
+        assert_eq!((x.get_data(), y.get_data()), (20_000, 20_000));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::txn::constants::DEFAULT_WATCH_CAPACITY;
+
+use parking_lot::{Condvar, Mutex};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+///
+/// Committed change of a transactional variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<T> {
+    /// Value written by the commit
+    pub value: T,
+    /// Write timestamp of the commit
+    pub stamp: u64,
+}
+
+struct State<T> {
+    /// Changes which aren't received yet, the oldest first
+    changes: VecDeque<Change<T>>,
+    /// Write timestamp of the latest change sent
+    latest: u64,
+    /// Number of the changes dropped because the queue was full
+    missed: u64,
+    /// Task waiting for the next change
+    waker: Option<Waker>,
+    /// Variable is dropped, no more changes will come
+    closed: bool,
+}
+
+struct Channel<T> {
+    state: Mutex<State<T>>,
+    cvar: Condvar,
+}
+
+impl<T> Channel<T> {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                changes: VecDeque::new(),
+                latest: 0,
+                missed: 0,
+                waker: None,
+                closed: false,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    ///
+    /// Delivers the given change, or closes the channel if there is none.
+    fn send(&self, change: Option<Change<T>>) {
+        let waker = {
+            let mut state = self.state.lock();
+            match change {
+                // Commit is overtaken by a later one which is sent already.
+                Some(change) if change.stamp < state.latest => return,
+                Some(change) => {
+                    state.latest = change.stamp;
+                    state.changes.push_back(change);
+                    if state.changes.len() > DEFAULT_WATCH_CAPACITY {
+                        state.changes.pop_front();
+                        state.missed += 1;
+                    }
+                }
+                None => state.closed = true,
+            }
+            state.waker.take()
+        };
+
+        self.cvar.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+///
+/// Subscriptions to the commits of a transactional variable, shared by all handles of it.
+pub(crate) struct Watchers<T> {
+    /// Number of the subscriptions, commits skip the notification when there are none
+    count: AtomicUsize,
+    channels: Mutex<Vec<Weak<Channel<T>>>>,
+}
+
+impl<T> Default for Watchers<T> {
+    fn default() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            channels: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> Watchers<T>
+where
+    T: Clone + Any + Send + Sync,
+{
+    pub(crate) fn subscribe(&self) -> Watch<T> {
+        let channel = Arc::new(Channel::new());
+
+        let mut channels = self.channels.lock();
+        channels.push(Arc::downgrade(&channel));
+        self.count.store(channels.len(), Ordering::SeqCst);
+
+        Watch { channel }
+    }
+
+    ///
+    /// Delivers the version committed at the given timestamp to the subscriptions. Dropped
+    /// subscriptions are forgotten.
+    pub(crate) fn notify(&self, value: &T, stamp: u64) {
+        if self.count.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        let mut channels = self.channels.lock();
+        channels.retain(|channel| match channel.upgrade() {
+            Some(channel) => {
+                channel.send(Some(Change {
+                    value: value.clone(),
+                    stamp,
+                }));
+                true
+            }
+            None => false,
+        });
+        self.count.store(channels.len(), Ordering::SeqCst);
+    }
+}
+
+impl<T> Drop for Watchers<T> {
+    fn drop(&mut self) {
+        for channel in self.channels.get_mut().iter().filter_map(Weak::upgrade) {
+            channel.send(None);
+        }
+    }
+}
+
+///
+/// Subscription to the commits of a transactional variable, taken with
+/// [TVar::watch](crate::txn::vars::TVar::watch).
+///
+/// Every commit that writes the variable after the subscription is delivered along with its
+/// write timestamp, once the commit is over. Changes are queued until they are received, and
+/// dropping the subscription stops the delivery. Once every handle of the variable is dropped,
+/// the queued changes are received and then the subscription is closed.
+///
+/// Changes are received in commit order. A change that is delivered after a later commit of
+/// the variable is skipped, the later one supersedes it. At most 1024 changes are queued, and
+/// when the subscriber lags behind, the oldest queued change is dropped for every new one, see
+/// [Watch::missed].
+pub struct Watch<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Watch<T> {
+    ///
+    /// Blocks until the next change. Returns `None` if the subscription is closed.
+    pub fn recv(&self) -> Option<Change<T>> {
+        let mut state = self.channel.state.lock();
+        loop {
+            if let Some(change) = state.changes.pop_front() {
+                return Some(change);
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            self.channel.cvar.wait(&mut state);
+        }
+    }
+
+    ///
+    /// Blocks until the next change for at most the given duration. Returns `None` if there
+    /// isn't any change in time or the subscription is closed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Change<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.channel.state.lock();
+        loop {
+            if let Some(change) = state.changes.pop_front() {
+                return Some(change);
+            }
+
+            if state.closed
+                || self
+                    .channel
+                    .cvar
+                    .wait_until(&mut state, deadline)
+                    .timed_out()
+            {
+                return state.changes.pop_front();
+            }
+        }
+    }
+
+    ///
+    /// Next change if there is one already.
+    pub fn try_recv(&self) -> Option<Change<T>> {
+        self.channel.state.lock().changes.pop_front()
+    }
+
+    ///
+    /// Number of the changes dropped so far because they weren't received in time and the
+    /// queue was full.
+    pub fn missed(&self) -> u64 {
+        self.channel.state.lock().missed
+    }
+
+    ///
+    /// Future of the next change, it completes with `None` if the subscription is closed.
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed {
+            channel: &self.channel,
+        }
+    }
+}
+
+///
+/// Future of the next change of a [Watch].
+pub struct Changed<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<'a, T> Future for Changed<'a, T> {
+    type Output = Option<Change<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.channel.state.lock();
+
+        if let Some(change) = state.changes.pop_front() {
+            return Poll::Ready(Some(change));
+        }
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use crate::txn::errors::*;
+    use crate::txn::fixture::txn;
+    use crate::txn::prelude::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn watch_commits() {
+        let mut tvar = TVar::new(100_u64);
+        let watch = tvar.watch();
+
+        let receiver = thread::spawn(move || (watch.recv(), watch));
+
+        txn().begin(|t| t.write(&mut tvar, 200)).unwrap();
+        let (change, watch) = receiver.join().unwrap();
+        assert_eq!(
+            change,
+            Some(Change {
+                value: 200,
+                stamp: tvar.committed().stamp
+            })
+        );
+
+        // Rolled back writes aren't delivered.
+        let res = txn().try_begin(|t| {
+            t.write(&mut tvar, 300);
+            Err::<(), _>(TxnError::Abort)
+        });
+        assert!(res.is_err());
+        assert_eq!(watch.recv_timeout(Duration::from_millis(10)), None);
+
+        // Changes are queued in commit order until the variable is gone.
+        txn().begin(|t| t.write(&mut tvar, 400)).unwrap();
+        txn().begin(|t| t.write(&mut tvar, 500)).unwrap();
+        drop(tvar);
+
+        assert_eq!(watch.try_recv().map(|c| c.value), Some(400));
+        assert_eq!(watch.recv().map(|c| c.value), Some(500));
+        assert_eq!(watch.recv(), None);
+    }
+
+    struct Wakes(AtomicUsize);
+
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn watch_changed() {
+        let mut tvar = TVar::new(100_u64);
+        let mut watch = tvar.watch();
+
+        let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut changed = watch.changed();
+        assert!(Pin::new(&mut changed).poll(&mut cx).is_pending());
+
+        txn().begin(|t| t.write(&mut tvar, 200)).unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+        match Pin::new(&mut changed).poll(&mut cx) {
+            Poll::Ready(Some(change)) => assert_eq!(change.value, 200),
+            _ => panic!("Change is not delivered."),
+        }
+
+        drop(tvar);
+        let mut changed = watch.changed();
+        assert!(matches!(
+            Pin::new(&mut changed).poll(&mut cx),
+            Poll::Ready(None)
+        ));
+    }
+
+    #[test]
+    fn watch_lagging() {
+        let mut tvar = TVar::new(0_u64);
+        let watch = tvar.watch();
+
+        for v in 1..=1100_u64 {
+            txn().begin(|t| t.write(&mut tvar, v)).unwrap();
+        }
+
+        // Oldest changes make room for the new ones.
+        assert_eq!(watch.missed(), 76);
+        assert_eq!(watch.try_recv().map(|c| c.value), Some(77));
+    }
+}
@@ -1,28 +1,20 @@
-use super::vars::TVar;
-use crate::sync::treiber::TreiberStack;
 use std::cell::RefCell;
-use std::{
-    borrow::{Borrow, BorrowMut},
-    collections::HashMap,
-    collections::HashSet,
-    ptr::NonNull,
-};
+use std::collections::HashMap;
 use std::{fmt, time::Duration};
 
-use super::utils;
 use crate::txn::conflicts::*;
+use itertools::Itertools;
 
-use crate::txn::version::{Var, Version};
-
-use std::any::Any;
+use crate::txn::ssi::SiReads;
+use crate::txn::version::Entry;
 
 thread_local! {
-    // real: LockVar<T>, T, virt: VersionWrite, VersionWrite
-    static LWS: RefCell<HashMap<Version, Version>> = RefCell::new(HashMap::new());
+    // real: TVar id, Entry of TVar<T> holding the written value
+    static LWS: RefCell<HashMap<u64, Entry>> = RefCell::new(HashMap::new());
 }
 
-// HashMap<*mut LockVar<T>, *mut T>
-pub struct WriteSet(HashMap<Version, Version>);
+#[derive(Clone)]
+pub struct WriteSet(HashMap<u64, Entry>);
 
 impl WriteSet {
     fn new() -> Self {
@@ -30,118 +22,105 @@ impl WriteSet {
     }
 
     pub fn local() -> Self {
-        let x = Self::new();
-        // dbg!(&x);
-        x
+        Self::new()
     }
 
-    pub fn get<T: Clone + Send + Sync>(&self, e: Var) -> Option<&Version> {
-        self.0.get(&Version::Write(e))
+    ///
+    /// Entry holding the value that is written to the variable with the given id in this
+    /// transaction.
+    pub(crate) fn get_by_id(&self, id: u64) -> Option<&Entry> {
+        self.0.get(&id)
     }
 
-    pub fn get_by_stamp<T: 'static + Clone + Send + Sync>(&self, stamp: u64) -> Option<&Version> {
-        self.0
-            .iter()
-            .find(|(tv, _)| {
-                let tvar: TVar<T> = utils::version_to_tvar(tv);
-                tvar.stamp == stamp
-            })
-            .map(|g| g.1)
-    }
-
-    pub fn put<T: 'static + Clone + Send + Sync>(&mut self, k: Var, v: Var) {
-        let kver = Version::Write(k);
-        let vver = Version::Write(v);
-
-        self.0.insert(kver, vver);
+    pub(crate) fn put(&mut self, k: Entry) {
+        // Variable that is already written keeps the revision it is staged first with.
+        let k = match self.0.remove(&k.id) {
+            Some(staged) => Entry {
+                stamp: staged.stamp,
+                ..k
+            },
+            None => k,
+        };
+
+        self.0.insert(k.id, k);
         LWS.with(|hs| {
             let mut hs = hs.borrow_mut();
             *hs = self.0.clone();
         });
     }
 
-    pub fn try_lock<T: 'static + Clone + Send + Sync>(&mut self, timeout: Duration) -> bool {
-        let ts = TreiberStack::<Var>::new();
-
-        for (k, _) in self.0.iter_mut() {
-            let read_val = k.read();
-            // utils::print_type_of(&read_val);
-            let v: Var = utils::direct_convert_ref(&read_val);
-            ts.push(v.clone());
-
-            let kv = TVar::new(v.clone());
-            if kv.lock.try_lock_for(timeout).is_some() {
-                ts.pop();
-            // TODO: Not sure if return false or just ignore
-            } else {
-                return false;
-            }
-        }
-
-        true
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 
-    pub fn get_all<T: 'static + Any + Clone + Send + Sync>(&self) -> Vec<(TVar<T>, T)> {
-        self.0
-            .iter()
-            .map(|(kp, vp)| {
-                let k: TVar<T> = utils::version_to_dest(kp);
-
-                let v: T = utils::version_to_dest(vp);
-
-                (k, v)
-            })
-            .collect()
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
-    pub fn get_all_keys<T: 'static + Clone + Send + Sync>(&self) -> Vec<TVar<T>> {
+    ///
+    /// Locks all variables in the write set, in the order of their ids to not deadlock with the
+    /// other committers.
+    pub fn try_lock(&mut self, timeout: Duration) -> bool {
         self.0
-            .keys()
-            .map(|p| {
-                let v: TVar<T> = utils::version_to_tvar(p);
-                v
-            })
-            .collect::<Vec<TVar<T>>>()
+            .values()
+            .sorted_by_key(|k| k.id)
+            .all(|k| k.lock(timeout))
     }
 
-    pub fn unlock<T: 'static + Clone + Send + Sync>(&self) {
-        self.get_all_keys().iter().for_each(|_x: &TVar<T>| {
-            // TODO: Store guards and drop here for convenience.
-            // Normally not needed, anyway.
-            // dbg!("Try unlock");
-            // unsafe { x.lock.force_unlock_fair(); }
-        })
+    ///
+    /// Ids of the variables in the write set.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.keys().copied()
+    }
+
+    pub(crate) fn get_all(&self) -> Vec<Entry> {
+        self.0.values().cloned().collect()
     }
 
-    pub(in crate::txn) fn writes_before<T: 'static + Clone + Send + Sync>(
-        &self,
-        rev: u64,
-    ) -> Vec<Compare> {
+    pub(in crate::txn) fn cmps(&self) -> Vec<Compare> {
         let mut wts = Vec::with_capacity(self.0.len());
-        self.0.iter().for_each(|(k, _v)| {
-            let var: TVar<T> = utils::version_to_tvar(k);
-            if var.modrev < rev {
-                let cmp = Compare::new(var.modrev, true, CompareSet::WriteLocal);
-                wts.push(cmp);
-            }
+        self.0.values().for_each(|k| {
+            let cmp = Compare::new(k.stamp, k.is_current(), CompareSet::WriteLocal);
+            wts.push(cmp);
         });
 
         wts
     }
 
-    pub fn clear<T: Clone + Send + Sync>(&mut self) {
+    ///
+    /// Serializable transactions that have read the variables in the write set.
+    pub(in crate::txn) fn readers(&self) -> Vec<&SiReads> {
+        self.0.values().map(Entry::readers).collect()
+    }
+
+    ///
+    /// Makes this set the write set of the ongoing transaction again. Writes that are staged
+    /// after this set is taken are discarded.
+    pub(crate) fn restore(self) {
+        LWS.with(|lws| {
+            let mut ws = lws.borrow_mut();
+            *ws = self.0;
+        })
+    }
+
+    ///
+    /// Exchanges the write set of the ongoing transaction with the given one.
+    pub(crate) fn swap_local(writes: &mut HashMap<u64, Entry>) {
+        LWS.with(|hs| std::mem::swap(&mut *hs.borrow_mut(), writes))
+    }
+
+    pub fn clear(&mut self) {
         // TODO: Drop all here from get_all
         self.0.clear();
         LWS.with(|lws| {
             let mut ws = lws.borrow_mut();
             *ws = self.0.clone();
-            // ws.clear();
         })
     }
 }
 
 impl fmt::Debug for WriteSet {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("WriteSet").field("ws", &self.0).finish()
+        f.debug_list().entries(self.0.keys()).finish()
     }
 }